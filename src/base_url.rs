@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// The 'base_url' from the config, split into the scheme and host, and any path the site is
+/// served from, e.g. 'https://example.github.io' and '/blog'. Neither part keeps a trailing slash,
+/// so URLs can be joined onto it without doubling up or missing slashes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BaseUrl {
+    origin: String,
+    path: String,
+}
+
+impl BaseUrl {
+    pub fn parse(input: &str) -> Result<BaseUrl> {
+        let input = input.trim();
+        let invalid = || {
+            anyhow!(
+                "[ ERROR ] base_url '{input}' must be a full url, like 'https://www.example.com/'"
+            )
+        };
+
+        let (scheme, rest) = input.split_once("://").ok_or_else(invalid)?;
+        if scheme != "http" && scheme != "https" {
+            return Err(invalid());
+        }
+        if rest.contains('?') || rest.contains('#') {
+            return Err(anyhow!(
+                "[ ERROR ] base_url '{input}' can't have a query or fragment"
+            ));
+        }
+
+        let (host, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+
+        let mut normalised_path: String = String::new();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            normalised_path.push('/');
+            normalised_path.push_str(segment);
+        }
+
+        Ok(BaseUrl {
+            origin: format!("{scheme}://{}", host.to_lowercase()),
+            path: normalised_path,
+        })
+    }
+
+    /// Checks if the site is served from a folder rather than the root of its domain.
+    pub fn has_path(&self) -> bool {
+        !self.path.is_empty()
+    }
+
+    /// Creates a full url from a path on the site, so '/blog/post.html' and 'blog/post.html'
+    /// both become 'https://www.example.com/blog/post.html'.
+    pub fn join(&self, path: &str) -> String {
+        format!(
+            "{}{}/{}",
+            self.origin,
+            self.path,
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Checks if a url like 'https://www.example.com/a.html' or '//www.example.com/' is on the
+    /// same host as the site, whatever path the site is served from.
+    pub fn is_same_host(&self, url: &str) -> bool {
+        let rest = match url.split_once("//") {
+            Some((scheme, rest)) if scheme.is_empty() || scheme.ends_with(':') => rest,
+            _ => return false,
+        };
+        let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+        let own_host = self.origin.split_once("://").map_or("", |(_, host)| host);
+
+        host.eq_ignore_ascii_case(own_host)
+    }
+
+    /// Turns a full url on this site back into a path, so 'https://www.example.com/blog/' is
+    /// '/blog/'. Returns None for urls on other sites.
+    pub fn to_site_path(&self, url: &str) -> Option<String> {
+        let rest = url.strip_prefix(&format!("{}{}", self.origin, self.path))?;
+
+        match rest.chars().next() {
+            None => Some(String::from("/")),
+            Some('/') => Some(rest.to_string()),
+            Some('?') | Some('#') => Some(format!("/{rest}")),
+            Some(_) => None,
+        }
+    }
+}
+
+impl Default for BaseUrl {
+    fn default() -> BaseUrl {
+        BaseUrl {
+            origin: String::from("https://www.example.com"),
+            path: String::new(),
+        }
+    }
+}
+
+/// Displays the base url with a trailing slash, as used in a '<base>' tag.
+impl fmt::Display for BaseUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}/", self.origin, self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let with_slash = BaseUrl::parse("https://www.example.com/").unwrap();
+        let without_slash = BaseUrl::parse("https://www.example.com").unwrap();
+        assert_eq!(with_slash, without_slash);
+        assert_eq!(with_slash.to_string(), "https://www.example.com/");
+
+        let subpath = BaseUrl::parse("http://Example.github.io//blog/").unwrap();
+        assert_eq!(subpath.to_string(), "http://example.github.io/blog/");
+
+        assert!(BaseUrl::parse("www.example.com").is_err());
+        assert!(BaseUrl::parse("ftp://www.example.com").is_err());
+        assert!(BaseUrl::parse("https://").is_err());
+        assert!(BaseUrl::parse("https://www.example.com/?page=1").is_err());
+    }
+
+    #[test]
+    fn join_test() {
+        let base_url = BaseUrl::parse("https://www.example.com/").unwrap();
+        let output = "https://www.example.com/blog/post.html";
+        assert_eq!(base_url.join("/blog/post.html"), output);
+        assert_eq!(base_url.join("blog/post.html"), output);
+        assert_eq!(base_url.join("/"), "https://www.example.com/");
+
+        let base_url = BaseUrl::parse("https://example.github.io/site").unwrap();
+        let output = "https://example.github.io/site/blog/post.html";
+        assert_eq!(base_url.join("/blog/post.html"), output);
+    }
+
+    #[test]
+    fn to_site_path_test() {
+        let base_url = BaseUrl::parse("https://example.github.io/site").unwrap();
+        let path = |url| base_url.to_site_path(url);
+        assert_eq!(
+            path("https://example.github.io/site/"),
+            Some(String::from("/"))
+        );
+        assert_eq!(
+            path("https://example.github.io/site"),
+            Some(String::from("/"))
+        );
+        assert_eq!(
+            path("https://example.github.io/site/a.html"),
+            Some(String::from("/a.html"))
+        );
+        assert_eq!(path("https://example.github.io/sitemap.xml"), None);
+        assert_eq!(path("https://github.com/"), None);
+    }
+
+    #[test]
+    fn is_same_host_test() {
+        let base_url = BaseUrl::parse("https://example.github.io/site").unwrap();
+        assert!(base_url.is_same_host("https://example.github.io/other/"));
+        assert!(base_url.is_same_host("http://Example.github.io"));
+        assert!(base_url.is_same_host("//example.github.io/a.js"));
+        assert!(!base_url.is_same_host("https://github.com/example.github.io"));
+        assert!(!base_url.is_same_host("/site/a.html"));
+    }
+}
@@ -0,0 +1,378 @@
+use pulldown_cmark::{CowStr, Event, Tag};
+use yaml_rust::Yaml;
+
+/// The kinds of callout GitHub supports, along with their titles
+const DEFAULT_CALLOUTS: [(&str, &str); 5] = [
+    ("note", "Note"),
+    ("tip", "Tip"),
+    ("important", "Important"),
+    ("warning", "Warning"),
+    ("caution", "Caution"),
+];
+
+/// Kinds of callout read from the 'callouts' section of the config, each with the title used
+/// when a callout doesn't give its own.
+#[derive(Clone, Debug)]
+pub struct CalloutConfig {
+    pub kinds: Vec<(String, String)>,
+}
+
+impl Default for CalloutConfig {
+    fn default() -> CalloutConfig {
+        CalloutConfig {
+            kinds: DEFAULT_CALLOUTS
+                .iter()
+                .map(|(kind, label)| (kind.to_string(), label.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl CalloutConfig {
+    pub fn from_yaml(yaml: &Yaml) -> CalloutConfig {
+        if yaml.as_bool() == Some(false) {
+            return CalloutConfig { kinds: Vec::new() };
+        }
+
+        match yaml.as_hash() {
+            Some(hash) => CalloutConfig {
+                kinds: hash
+                    .iter()
+                    .filter_map(|(kind, label)| {
+                        let kind = kind.as_str()?.to_lowercase();
+                        let label = label.as_str().map_or(kind.to_string(), String::from);
+                        Some((kind, label))
+                    })
+                    .collect(),
+            },
+            None => CalloutConfig::default(),
+        }
+    }
+
+    fn label_for(&self, kind: &str) -> Option<&str> {
+        self.kinds
+            .iter()
+            .find(|(k, _)| k == kind)
+            .map(|(_, label)| label.as_str())
+    }
+}
+
+/// Puts blank lines around ':::' fences outside of code blocks, so each fence is parsed as its
+/// own paragraph whatever is written next to it. Only fences at the start of a line are used, so
+/// those in lists, blockquotes and indented code are left alone.
+pub fn separate_callout_fences(input: &str) -> String {
+    let mut output: String = String::with_capacity(input.len());
+    // The character and length of the code fence the line is in
+    let mut code_fence: Option<(char, usize)> = None;
+
+    for line in input.split_inclusive('\n') {
+        match code_fence {
+            Some(fence) => {
+                if get_code_fence(line).map_or(false, |(c, len)| {
+                    c == fence.0
+                        && len >= fence.1
+                        && line.trim_start().trim_start_matches(c).trim().is_empty()
+                }) {
+                    code_fence = None;
+                }
+            }
+            None if line.starts_with(":::") => {
+                output.push('\n');
+                output.push_str(line.trim_end());
+                output.push_str("\n\n");
+                continue;
+            }
+            None => code_fence = get_code_fence(line),
+        }
+
+        output.push_str(line);
+    }
+
+    output
+}
+
+/// Gets the character and length of a code fence starting a line, like '```rust'.
+fn get_code_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let c = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.len() - trimmed.trim_start_matches(c).len();
+    match len >= 3 {
+        true => Some((c, len)),
+        false => None,
+    }
+}
+
+/// Turns blockquotes starting with '[!NOTE]', and paragraphs between ':::note' and ':::', into
+/// an '<aside>' with a title. Any text after the kind is used as the title instead of its label.
+pub fn add_callouts<'a>(events: Vec<Event<'a>>, config: &CalloutConfig) -> Vec<Event<'a>> {
+    if config.kinds.is_empty() {
+        return events;
+    }
+
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    // Whether each open blockquote is a callout, so its end can be replaced too
+    let mut blockquotes: Vec<bool> = Vec::new();
+    let mut open_fences: usize = 0;
+    // Fences are only read outside of lists and blockquotes
+    let mut containers: usize = 0;
+    let mut i: usize = 0;
+
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(tag) if is_container(tag) => containers += 1,
+            Event::End(tag) if is_container(tag) => containers = containers.saturating_sub(1),
+            _ => (),
+        }
+
+        match &events[i] {
+            Event::Start(Tag::BlockQuote) => {
+                match get_first_line(&events, i + 2)
+                    .filter(|_| events.get(i + 1) == Some(&Event::Start(Tag::Paragraph)))
+                    .and_then(|(line, end)| Some((parse_marker(&line, config)?, end)))
+                {
+                    Some(((kind, title), end)) => {
+                        output.push(create_callout_start(&kind, &title));
+                        blockquotes.push(true);
+                        // Keep the rest of the paragraph if there is more after the marker
+                        if events[end] != Event::End(Tag::Paragraph) {
+                            output.push(Event::Start(Tag::Paragraph));
+                        }
+                        i = end + 1;
+                        continue;
+                    }
+                    None => blockquotes.push(false),
+                }
+            }
+            Event::End(Tag::BlockQuote) => {
+                match blockquotes.pop() {
+                    Some(true) => output.push(Event::Html(CowStr::from("</aside>\n"))),
+                    _ => output.push(events[i].clone()),
+                }
+                i += 1;
+                continue;
+            }
+            Event::Start(Tag::Paragraph) if containers == 0 => {
+                if let Some((line, end)) = get_first_line(&events, i + 1) {
+                    let fence = line.trim().strip_prefix(":::");
+                    if events[end] == Event::End(Tag::Paragraph) {
+                        match fence.map(str::trim) {
+                            Some("") if open_fences > 0 => {
+                                output.push(Event::Html(CowStr::from("</aside>\n")));
+                                open_fences -= 1;
+                                i = end + 1;
+                                continue;
+                            }
+                            Some(fence) => {
+                                let (kind, title) = fence.split_once(' ').unwrap_or((fence, ""));
+                                let kind = kind.to_lowercase();
+                                if let Some(label) = config.label_for(&kind) {
+                                    let title = title.trim();
+                                    let title = if title.is_empty() { label } else { title };
+                                    output.push(create_callout_start(&kind, title));
+                                    open_fences += 1;
+                                    i = end + 1;
+                                    continue;
+                                }
+                            }
+                            None => (),
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        output.push(events[i].clone());
+        i += 1;
+    }
+
+    // Close any fences which weren't closed before the end of the page
+    for _ in 0..open_fences {
+        output.push(Event::Html(CowStr::from("</aside>\n")));
+    }
+
+    output
+}
+
+fn is_container(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::BlockQuote | Tag::List(_) | Tag::Item | Tag::FootnoteDefinition(_)
+    )
+}
+
+/// Gets the text of a paragraph up to its first line break, as long as it starts with text.
+/// Returns the text and the index of the event that ended the line.
+fn get_first_line(events: &[Event], start: usize) -> Option<(String, usize)> {
+    if !matches!(events.get(start), Some(Event::Text(_))) {
+        return None;
+    }
+
+    let mut line: String = String::new();
+    for (i, event) in events.iter().enumerate().skip(start) {
+        match event {
+            Event::Text(text) | Event::Code(text) => line.push_str(text),
+            Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph) => {
+                return Some((line, i))
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Removes the '[!NOTE]' marker from the start of a blockquote's text, keeping any title after
+/// it, for text without any markup like the search index.
+pub fn strip_callout_marker(text: &str) -> &str {
+    let marker = text
+        .trim_start()
+        .strip_prefix("[!")
+        .and_then(|rest| rest.split_once(']'))
+        .filter(|(kind, _)| !kind.is_empty() && kind.chars().all(char::is_alphanumeric));
+
+    match marker {
+        Some((_, title)) => title,
+        None => text,
+    }
+}
+
+/// Reads the kind and title from a line like '[!WARNING] Custom title'.
+fn parse_marker(line: &str, config: &CalloutConfig) -> Option<(String, String)> {
+    let rest = line.trim_start().strip_prefix("[!")?;
+    let (kind, title) = rest.split_once(']')?;
+    let kind = kind.to_lowercase();
+    let label = config.label_for(&kind)?;
+
+    let title = match title.trim().is_empty() {
+        true => label.to_string(),
+        false => title.trim().to_string(),
+    };
+    Some((kind, title))
+}
+
+fn create_callout_start<'a>(kind: &str, title: &str) -> Event<'a> {
+    Event::Html(CowStr::from(format!(
+        "<aside class=\"callout callout-{kind}\">\n<p class=\"callout-title\">{}</p>\n",
+        escape_html(title)
+    )))
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(input: &str, config: &CalloutConfig) -> String {
+        let input = separate_callout_fences(input);
+        let events = add_callouts(Parser::new(&input).collect(), config);
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+        output
+    }
+
+    #[test]
+    fn blockquote_callout_test() {
+        let config = CalloutConfig::default();
+
+        let input = "> [!NOTE]\n> Some *useful* text.\n";
+        let answer = "\
+<aside class=\"callout callout-note\">
+<p class=\"callout-title\">Note</p>
+<p>Some <em>useful</em> text.</p>
+</aside>
+";
+        assert_eq!(render(input, &config), answer);
+
+        let input = "> [!warning] Be careful\n>\n> Really.\n";
+        let answer = "\
+<aside class=\"callout callout-warning\">
+<p class=\"callout-title\">Be careful</p>
+<p>Really.</p>
+</aside>
+";
+        assert_eq!(render(input, &config), answer);
+
+        let input = "> [!UNKNOWN]\n> Just a quote.\n";
+        assert!(render(input, &config).starts_with("<blockquote>"));
+    }
+
+    #[test]
+    fn fenced_callout_test() {
+        let config = CalloutConfig::from_yaml(
+            &yaml_rust::YamlLoader::load_from_str("tip: Hint").unwrap()[0],
+        );
+
+        let input = ":::tip\nTry this:\n\n- one\n- two\n:::\n\n```\n:::tip\n```\n";
+        let answer = "\
+<aside class=\"callout callout-tip\">
+<p class=\"callout-title\">Hint</p>
+<p>Try this:</p>
+<ul>
+<li>one</li>
+<li>two</li>
+</ul>
+</aside>
+<pre><code>:::tip
+</code></pre>
+";
+        assert_eq!(render(input, &config), answer);
+
+        let input = "> [!NOTE]\n> Not a kind in the config\n";
+        assert!(render(input, &config).starts_with("<blockquote>"));
+    }
+
+    #[test]
+    fn nested_and_unclosed_fences_test() {
+        let config = CalloutConfig::default();
+
+        let input = ":::note\nOuter\n:::tip\nInner\n:::\n:::\n";
+        let answer = "\
+<aside class=\"callout callout-note\">
+<p class=\"callout-title\">Note</p>
+<p>Outer</p>
+<aside class=\"callout callout-tip\">
+<p class=\"callout-title\">Tip</p>
+<p>Inner</p>
+</aside>
+</aside>
+";
+        assert_eq!(render(input, &config), answer);
+
+        let input = "````\n```\n:::note\n````\n\n- :::note\n\n> :::note\n\n    :::note\n";
+        let answer = "\
+<pre><code>```
+:::note
+</code></pre>
+<ul>
+<li>:::note</li>
+</ul>
+<blockquote>
+<p>:::note</p>
+</blockquote>
+<pre><code>:::note
+</code></pre>
+";
+        assert_eq!(render(input, &config), answer);
+
+        let input = ":::warning\nNever closed\n";
+        let answer = "\
+<aside class=\"callout callout-warning\">
+<p class=\"callout-title\">Warning</p>
+<p>Never closed</p>
+</aside>
+";
+        assert_eq!(render(input, &config), answer);
+    }
+}
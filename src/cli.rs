@@ -23,6 +23,15 @@ pub enum Commands {
     Build,
     /// Create a new file within the 'content/' folder with default frontmatter
     New { file: String },
+    /// Check that the links in the built site in 'site/' all lead somewhere
+    CheckLinks {
+        /// List every link to another site, without checking them
+        #[clap(long)]
+        list_external: bool,
+        /// Also check links to other sites, which requests each one
+        #[clap(long)]
+        check_external: bool,
+    },
 }
 
 #[derive(Parser, Copy, Clone, ArgEnum)]
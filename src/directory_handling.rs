@@ -1,21 +1,43 @@
 use anyhow::{anyhow, Result};
 use fs_extra::copy_items;
 use fs_extra::dir::{get_dir_content, get_dir_content2, CopyOptions, DirOptions};
+use std::collections::HashSet;
 use std::env::{current_dir, set_current_dir};
-use std::fs::{create_dir_all, read_dir, File};
+use std::fs::{self, create_dir_all, read_dir, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::feeds::{create_feed_links, write_feeds};
+use crate::highlighting::write_highlight_css;
+use crate::link_checker::{check_links, ExternalLinks};
+use crate::markdown_compiling::Page;
 use crate::page_creation::create_index_page;
+use crate::permalinks::get_output_file;
+use crate::redirects::write_redirects;
+use crate::search::write_search_index;
 use crate::site_data::Site;
+use crate::sitemap::{write_robots, write_sitemap};
+
+pub const NOT_FOUND_PATH: &str = "content/404.md";
+
+/// Used for 'site/404.html' when there isn't a 'content/404.md' or 'static/404.html'
+const NOT_FOUND_PAGE: &str = "\
+---
+title: Page not found
+description: The page you were looking for could not be found
+category: home
+noindex: true
+---
+# Page not found
+
+Sorry, the page you were looking for doesn't exist. Try going back to the [home page](/index.html).
+";
 
 pub fn process_content() -> Result<()> {
-    let content_dir = get_dir_content("content/")?;
+    let content_files = get_content_files()?;
     let mut site: Site = Site::new()?;
 
-    // for file in content_dir.files {}
-
-    for file in content_dir.files {
+    for file in content_files.pages {
         println!("[ INFO ] Processing {file}");
         site.add_page(&file)?;
     }
@@ -32,20 +54,135 @@ pub fn process_content() -> Result<()> {
         }
     }
 
+    // Copied before rendering, so the size of images can be read from 'site/'
+    copy_content_files(&site, &content_files.others)?;
+    site.render_pages()?;
     let pages = site.pages.to_vec();
 
     for mut page in pages {
         println!("[ INFO ] Writing {}", &page.filepath);
         page.replace_index(&mut site);
-        page.replace_navbar(&nav_links);
-        page.replace_site_name(&site.site_name);
-        page.replace_base_url(&site.base_url);
-        page.write_to_file()?;
+        write_page(page, &site, &nav_links)?;
+    }
+
+    write_not_found_page(&site, &nav_links)?;
+    write_redirects(&site)?;
+    write_feeds(&site)?;
+    write_sitemap(&site)?;
+    write_robots(&site)?;
+    write_search_index(&site)?;
+    write_highlight_css(&site)?;
+
+    if site.check_links {
+        let broken = check_links(&site, ExternalLinks::Ignore)?;
+        if broken > 0 {
+            let message = format!("Found {broken} broken links");
+            site.broken_links.report(&message, "site/")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The files in 'content/', split into the markdown files which are built into pages and the
+/// other files, like images, which are copied next to them
+#[derive(Debug, Default, PartialEq)]
+pub struct ContentFiles {
+    pub pages: Vec<String>,
+    pub others: Vec<String>,
+}
+
+/// Reads the files in 'content/'. 'content/404.md' is left out, since it is written on its own.
+pub fn get_content_files() -> Result<ContentFiles> {
+    Ok(split_content_files(get_dir_content("content/")?.files))
+}
+
+fn split_content_files(files: Vec<String>) -> ContentFiles {
+    let mut content_files = ContentFiles::default();
+
+    for file in files {
+        if file == NOT_FOUND_PATH {
+            continue;
+        }
+        match file.ends_with(".md") {
+            true => content_files.pages.push(file),
+            false => content_files.others.push(file),
+        }
+    }
+
+    content_files
+}
+
+/// Copies the files in 'content/' which aren't pages into the folder each page beside them is
+/// written to, so relative links from a page to them keep working.
+fn copy_content_files(site: &Site, files: &[String]) -> Result<()> {
+    for (file, destination) in get_content_file_copies(&site.pages, files) {
+        println!("[ INFO ] Copying {file}");
+        if let Some(parent) = destination.parent() {
+            check_and_create_directory(&parent.display().to_string())?;
+        }
+        fs::copy(&file, &destination)
+            .map_err(|e| anyhow!("[ ERROR ] Could not copy {file}: {e}"))?;
     }
 
     Ok(())
 }
 
+/// Gets each place in 'site/' a file in 'content/' is copied to, which is the output folder of
+/// every page in the same folder as it.
+fn get_content_file_copies(pages: &[Page], files: &[String]) -> Vec<(String, PathBuf)> {
+    let mut copies: Vec<(String, PathBuf)> = Vec::new();
+    let mut destinations: HashSet<PathBuf> = HashSet::new();
+
+    for page in pages {
+        let content_dir = Path::new(&page.filepath).parent();
+        let output_file = get_output_file(&page.url);
+        let output_dir = Path::new(&output_file)
+            .parent()
+            .unwrap_or_else(|| Path::new("site"));
+
+        for file in files {
+            let path = Path::new(file);
+            if path.parent() != content_dir {
+                continue;
+            }
+            if let Some(name) = path.file_name() {
+                let destination = output_dir.join(name);
+                if destinations.insert(destination.clone()) {
+                    copies.push((file.to_string(), destination));
+                }
+            }
+        }
+    }
+
+    copies
+}
+
+fn write_page(mut page: Page, site: &Site, nav_links: &str) -> Result<()> {
+    page.replace_navbar(nav_links);
+    page.replace_feed_links(&create_feed_links(site, &page.category));
+    page.replace_site_name(&site.site_name);
+    page.replace_base_url(&site.base_url.to_string());
+    page.write_to_file()
+}
+
+/// Writes 'site/404.html' from 'content/404.md', or from a default page if it doesn't exist.
+/// It isn't added to the site's pages, so it is left out of indexes, the sitemap and feeds.
+fn write_not_found_page(site: &Site, nav_links: &str) -> Result<()> {
+    let mut page: Page = if Path::new(NOT_FOUND_PATH).exists() {
+        println!("[ INFO ] Writing {NOT_FOUND_PATH}");
+        Page::from_file(NOT_FOUND_PATH, site)?
+    } else if Path::new("static/404.html").exists() {
+        return Ok(());
+    } else {
+        Page::from_source(NOT_FOUND_PATH, NOT_FOUND_PAGE, site)?
+    };
+
+    page.url = String::from("/404.html");
+    page.content_to_html(site)?;
+    write_page(page, site, nav_links)
+}
+
 pub fn move_to_project_root() -> Result<()> {
     let starting_dir: PathBuf = current_dir()?;
 
@@ -107,6 +244,18 @@ pub fn check_and_create_directory(dir: &str) -> Result<()> {
     }
 }
 
+/// Writes a generated file into 'site/', creating any parent directories it needs.
+pub fn write_site_file(path: &str, content: &str) -> Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        check_and_create_directory(&parent.display().to_string())?;
+    }
+
+    let mut outfile = File::create(path)?;
+    outfile.write_all(content.as_bytes())?;
+
+    Ok(())
+}
+
 /* The code that was used to figure out how to download and unzip a file was taken from this stack
 * overflow answer;
 * https://stackoverflow.com/a/50471953 */
@@ -147,6 +296,89 @@ static_url: https://github.com/SoftAnnaLee/md_puppy/releases/download/static/sta
 boilerplate_url: https://raw.githubusercontent.com/SoftAnnaLee/md_puppy/main/template/boilerplate.html
 # Base Url used for relative links
 base_url: https://www.example.com/
+# Place pages in 'site/' using their folder in 'content/' instead of their category
+mirror_content_paths: false
+# Create URLs from the title of each page instead of its filename
+slug_from_title: false
+# Also write a '_redirects' file for page aliases, for hosts which support it
+redirects_file: false
+# Write feeds of the newest pages to 'site/', using any of rss, atom and json
+feeds:
+  enabled: true
+  formats: [rss]
+  per_category: false
+  full_content: false
+# Write a sitemap of every page to 'site/sitemap.xml'
+sitemap: true
+# Author of the site, used when a page doesn't have its own
+author: \"\"
+# Image shown in link previews when a page doesn't have its own
+default_image: \"\"
+# Write an index of every page to 'site/search.json' for searching the site
+search:
+  enabled: false
+  fields: [title, url, category, date, description, body]
+# Headings included in a page's table of contents
+toc:
+  min_level: 2
+  max_level: 3
+# Markdown extensions, pages can change these with a 'markdown' section in their frontmatter
+markdown:
+  tables: true
+  footnotes: true
+  strikethrough: true
+  tasklists: true
+  smart_punctuation: true
+  heading_attributes: true
+  # Convert '$...$' and '$$...$$' math to MathML
+  math: false
+  callouts: true
+  wiki_links: true
+  markdown_links: true
+  highlight: true
+  # Add a '#' link to each heading
+  heading_anchors: false
+# Highlight code blocks using classes and 'site/css/highlight.css', or inline styles
+highlight:
+  enabled: true
+  style: classes
+  theme: InspiredGitHub
+  line_numbers: false
+# Warn about links to pages which don't exist, or stop the build with 'error'
+broken_links: warn
+# Check the links in 'site/' after building, like 'md_puppy check-links'
+check_links: false
+# Attributes added to links to other sites, put '{.internal}' after a link to leave it alone
+external_links:
+  enabled: false
+  rel: noopener noreferrer
+  new_tab: true
+  class: external
+  icon: \"\"
+# Write images with a title on their own line as a figure with a caption, lazy load images and
+# add their width and height
+images:
+  figures: true
+  lazy_loading: true
+  dimensions: true
+# Words read per minute, used for '{{reading_time}}'
+words_per_minute: 200
+# Show these details after each page in category index pages
+category_index:
+  summary: false
+  word_count: false
+  reading_time: false
+# Kinds of callout, used like '> [!NOTE]' or ':::note', and their titles
+callouts:
+  note: Note
+  tip: Tip
+  important: Important
+  warning: Warning
+  caution: Caution
+# Uncomment to write 'site/robots.txt' with these rules
+# robots:
+#   user_agent: \"*\"
+#   disallow: [/drafts/]
 ".to_string();
 
         let mut outfile =
@@ -183,6 +415,57 @@ mod tests {
     //     assert!(read_dir("static").is_ok());
     // }
 
+    #[test]
+    fn split_content_files_test() {
+        let files = vec![
+            String::from("content/index.md"),
+            String::from(NOT_FOUND_PATH),
+            String::from("content/blog/beach.png"),
+            String::from("content/blog/trip.md"),
+        ];
+        assert_eq!(
+            split_content_files(files),
+            ContentFiles {
+                pages: vec![
+                    String::from("content/index.md"),
+                    String::from("content/blog/trip.md")
+                ],
+                others: vec![String::from("content/blog/beach.png")],
+            }
+        );
+    }
+
+    #[test]
+    fn get_content_file_copies_test() {
+        let mut trip = Page::new();
+        trip.filepath = String::from("content/blog/trip.md");
+        trip.url = String::from("/2021/05/trip/");
+        let mut notes = Page::new();
+        notes.filepath = String::from("content/blog/notes.md");
+        notes.url = String::from("/blog/notes.html");
+        let mut other = Page::new();
+        other.filepath = String::from("content/blog/more/other.md");
+        other.url = String::from("/blog/other.html");
+
+        let files = vec![
+            String::from("content/blog/beach.png"),
+            String::from("content/photo.jpg"),
+        ];
+        assert_eq!(
+            get_content_file_copies(&[trip, notes, other], &files),
+            vec![
+                (
+                    String::from("content/blog/beach.png"),
+                    PathBuf::from("site/2021/05/trip/beach.png")
+                ),
+                (
+                    String::from("content/blog/beach.png"),
+                    PathBuf::from("site/blog/beach.png")
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn create_directory_test() {
         assert!(check_and_create_directory("site/").is_ok());
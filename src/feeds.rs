@@ -0,0 +1,517 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local};
+use serde_json::{json, Value};
+use std::fs;
+use yaml_rust::Yaml;
+
+use crate::directory_handling::write_site_file;
+use crate::markdown_compiling::Page;
+use crate::site_data::{parse_date, Site};
+
+/// Date format used by RSS, written out so days always have two digits
+const RFC_822: &str = "%a, %d %b %Y %H:%M:%S %z";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    pub fn from_name(name: &str) -> Option<FeedFormat> {
+        match name.to_lowercase().as_str() {
+            "rss" => Some(FeedFormat::Rss),
+            "atom" => Some(FeedFormat::Atom),
+            "json" => Some(FeedFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn filename(&self) -> &str {
+        match self {
+            FeedFormat::Rss => "rss.xml",
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    pub fn mime_type(&self) -> &str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml",
+            FeedFormat::Atom => "application/atom+xml",
+            FeedFormat::Json => "application/feed+json",
+        }
+    }
+}
+
+/// Options read from the 'feeds' section of the config
+#[derive(Clone, Debug)]
+pub struct FeedConfig {
+    pub enabled: bool,
+    pub formats: Vec<FeedFormat>,
+    pub limit: Option<usize>,
+    pub per_category: bool,
+    pub full_content: bool,
+}
+
+impl Default for FeedConfig {
+    fn default() -> FeedConfig {
+        FeedConfig {
+            enabled: true,
+            formats: vec![FeedFormat::Rss],
+            limit: None,
+            per_category: false,
+            full_content: false,
+        }
+    }
+}
+
+impl FeedConfig {
+    pub fn from_yaml(yaml: &Yaml) -> FeedConfig {
+        let default = FeedConfig::default();
+
+        if let Some(enabled) = yaml.as_bool() {
+            return FeedConfig { enabled, ..default };
+        }
+
+        let formats: Vec<FeedFormat> = match yaml["formats"].as_vec() {
+            Some(names) => names
+                .iter()
+                .filter_map(|name| {
+                    let format = name.as_str().and_then(FeedFormat::from_name);
+                    if format.is_none() {
+                        println!("[ WARN ] Unknown feed format {name:?}, use rss, atom or json");
+                    }
+                    format
+                })
+                .collect(),
+            None => default.formats,
+        };
+
+        FeedConfig {
+            enabled: yaml["enabled"].as_bool().unwrap_or(default.enabled),
+            formats,
+            limit: yaml["limit"].as_i64().map(|limit| limit.max(0) as usize),
+            per_category: yaml["per_category"]
+                .as_bool()
+                .unwrap_or(default.per_category),
+            full_content: yaml["full_content"]
+                .as_bool()
+                .unwrap_or(default.full_content),
+        }
+    }
+}
+
+/// Writes a feed in each format to 'site/', along with one in 'site/<category>/' for every
+/// category if 'per_category' is enabled.
+pub fn write_feeds(site: &Site) -> Result<()> {
+    if !site.feeds.enabled {
+        return Ok(());
+    }
+
+    write_feed_formats(site, None, &site.site_name)?;
+
+    if site.feeds.per_category {
+        for cat in &site.categories {
+            let title = format!("{} - {cat}", site.site_name);
+            write_feed_formats(site, Some(cat), &title)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_feed_formats(site: &Site, category: Option<&str>, title: &str) -> Result<()> {
+    let mut pages = site.feed_pages(category);
+    if let Some(limit) = site.feeds.limit {
+        pages.truncate(limit);
+    }
+
+    for format in &site.feeds.formats {
+        let feed_path = get_feed_path(*format, category);
+        let output = match format {
+            FeedFormat::Rss => create_rss(site, &pages, title, &feed_path),
+            FeedFormat::Atom => create_atom(site, &pages, title, &feed_path),
+            FeedFormat::Json => create_json_feed(site, &pages, title, &feed_path),
+        };
+        write_site_file(&format!("site{feed_path}"), &output)?;
+    }
+
+    Ok(())
+}
+
+fn get_feed_path(format: FeedFormat, category: Option<&str>) -> String {
+    match category {
+        Some(cat) => format!("/{cat}/{}", format.filename()),
+        None => format!("/{}", format.filename()),
+    }
+}
+
+/// Creates the '<link rel="alternate">' tags for the feeds of the whole site, and for the
+/// category of a page if it has its own feeds.
+pub fn create_feed_links(site: &Site, category: &str) -> String {
+    let mut output: String = String::new();
+
+    if !site.feeds.enabled {
+        return output;
+    }
+
+    let cat = category.to_lowercase();
+    let mut feeds: Vec<(Option<&str>, String)> = vec![(None, site.site_name.to_string())];
+    if site.feeds.per_category && site.categories.contains(&cat) {
+        feeds.push((Some(&cat), format!("{} - {cat}", site.site_name)));
+    }
+
+    for (category, title) in feeds {
+        for format in &site.feeds.formats {
+            output.push_str(&format!(
+                "<link rel=\"alternate\" type=\"{}\" title=\"{}\" href=\"{}\">\n",
+                format.mime_type(),
+                escape_xml(&title),
+                get_feed_path(*format, category)
+            ));
+        }
+    }
+
+    output
+}
+
+pub fn create_rss(site: &Site, pages: &[&Page], title: &str, feed_path: &str) -> String {
+    let title = escape_xml(title);
+    let site_link = escape_xml(&site.absolute_url("/"));
+    let feed_link = escape_xml(&site.absolute_url(feed_path));
+
+    let mut output: String = format!(
+        "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:content=\"http://purl.org/rss/1.0/modules/content/\">
+<channel>
+<title>{title}</title>
+<link>{site_link}</link>
+<description>{title}</description>
+<atom:link href=\"{feed_link}\" rel=\"self\" type=\"application/rss+xml\"/>
+"
+    );
+
+    if let Some(date) = pages.first().and_then(|page| parse_date(&page.date)) {
+        output.push_str(&format!(
+            "<lastBuildDate>{}</lastBuildDate>\n",
+            date.format(RFC_822)
+        ));
+    }
+
+    for page in pages {
+        output.push_str(&create_rss_item(site, page));
+    }
+
+    output.push_str("</channel>\n</rss>\n");
+    output
+}
+
+fn create_rss_item(site: &Site, page: &Page) -> String {
+    let link = escape_xml(&site.absolute_url(&page.url));
+    let mut item: String = format!(
+        "\
+<item>
+<title>{}</title>
+<link>{link}</link>
+<guid isPermaLink=\"true\">{link}</guid>
+<description>{}</description>
+<category>{}</category>
+",
+        escape_xml(&page.title),
+        escape_xml(&page.description),
+        escape_xml(&page.category)
+    );
+
+    if let Some(date) = parse_date(&page.date) {
+        item.push_str(&format!("<pubDate>{}</pubDate>\n", date.format(RFC_822)));
+    }
+
+    if site.feeds.full_content {
+        item.push_str(&format!(
+            "<content:encoded>{}</content:encoded>\n",
+            escape_xml(&feed_html(site, page))
+        ));
+    }
+
+    item.push_str("</item>\n");
+    item
+}
+
+pub fn create_atom(site: &Site, pages: &[&Page], title: &str, feed_path: &str) -> String {
+    let title = escape_xml(title);
+    let site_link = escape_xml(&site.absolute_url("/"));
+    let feed_link = escape_xml(&site.absolute_url(feed_path));
+    let updated = match pages.first().and_then(|page| parse_date(&page.date)) {
+        Some(date) => date.to_rfc3339(),
+        None => Local::now().to_rfc3339(),
+    };
+
+    let mut output: String = format!(
+        "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+<title>{title}</title>
+<link href=\"{site_link}\"/>
+<link href=\"{feed_link}\" rel=\"self\" type=\"application/atom+xml\"/>
+<id>{site_link}</id>
+<updated>{updated}</updated>
+<author><name>{}</name></author>
+",
+        escape_xml(&site.site_name)
+    );
+
+    for page in pages {
+        output.push_str(&create_atom_entry(site, page));
+    }
+
+    output.push_str("</feed>\n");
+    output
+}
+
+fn create_atom_entry(site: &Site, page: &Page) -> String {
+    let link = escape_xml(&site.absolute_url(&page.url));
+    let date = get_atom_date(page).to_rfc3339();
+    let updated = parse_date(&page.updated)
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_else(|| date.to_string());
+    let mut entry: String = format!(
+        "\
+<entry>
+<title>{}</title>
+<link href=\"{link}\"/>
+<id>{link}</id>
+<published>{date}</published>
+<updated>{updated}</updated>
+<summary>{}</summary>
+<category term=\"{}\"/>
+",
+        escape_xml(&page.title),
+        escape_xml(&page.description),
+        escape_xml(&page.category)
+    );
+
+    if site.feeds.full_content {
+        entry.push_str(&format!(
+            "<content type=\"html\">{}</content>\n",
+            escape_xml(&feed_html(site, page))
+        ));
+    }
+
+    entry.push_str("</entry>\n");
+    entry
+}
+
+/// Gets the date of a page for Atom, which needs every entry to have one. Pages without a date
+/// use the time their file was last changed, or the time the site was built.
+fn get_atom_date(page: &Page) -> DateTime<FixedOffset> {
+    if let Some(date) = parse_date(&page.date) {
+        return date;
+    }
+
+    let modified: DateTime<Local> = match fs::metadata(&page.filepath).and_then(|m| m.modified()) {
+        Ok(modified) => modified.into(),
+        Err(_) => Local::now(),
+    };
+    modified.into()
+}
+
+/// Creates a feed following version 1.1 of the JSON Feed spec, https://jsonfeed.org/version/1.1
+pub fn create_json_feed(site: &Site, pages: &[&Page], title: &str, feed_path: &str) -> String {
+    let items: Vec<Value> = pages
+        .iter()
+        .map(|page| {
+            let url = site.absolute_url(&page.url);
+            let mut item = json!({
+                "id": url,
+                "url": url,
+                "title": page.title,
+                "summary": page.description,
+                "tags": [page.category],
+            });
+
+            if let Some(date) = parse_date(&page.date) {
+                item["date_published"] = json!(date.to_rfc3339());
+            }
+
+            if site.feeds.full_content {
+                item["content_html"] = json!(feed_html(site, page));
+            } else {
+                item["content_text"] = json!(page.description);
+            }
+
+            item
+        })
+        .collect();
+
+    let feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": title,
+        "home_page_url": site.absolute_url("/"),
+        "feed_url": site.absolute_url(feed_path),
+        "items": items,
+    });
+
+    // Serialising a 'Value' can't fail, since all of its keys are strings
+    serde_json::to_string_pretty(&feed).unwrap_or_default()
+}
+
+/// Gets the html of a page with every link and image changed to a full URL, since feed readers
+/// don't know where the page is on the site.
+fn feed_html(site: &Site, page: &Page) -> String {
+    let mut output: String = String::with_capacity(page.html.len());
+    let mut rest: &str = &page.html;
+
+    while let Some((start, quote)) = find_url_attribute(rest) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(quote).unwrap_or(rest.len());
+        output.push_str(&absolute_feed_url(site, page, &rest[..end]));
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Finds where the value of the next 'href' or 'src' attribute starts, along with its quote.
+fn find_url_attribute(html: &str) -> Option<(usize, char)> {
+    ["href=\"", "href='", "src=\"", "src='"]
+        .iter()
+        .filter_map(|pattern| {
+            let mut offset: usize = 0;
+            // Skip attributes like 'data-src' which aren't links
+            while let Some(i) = html[offset..].find(pattern) {
+                let start = offset + i;
+                if html[..start].ends_with(char::is_whitespace) {
+                    return Some((start + pattern.len(), pattern.chars().last()?));
+                }
+                offset = start + pattern.len();
+            }
+            None
+        })
+        .min_by_key(|(start, _)| *start)
+}
+
+fn absolute_feed_url(site: &Site, page: &Page, url: &str) -> String {
+    let has_scheme = url.split_once(':').map_or(false, |(scheme, _)| {
+        scheme.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+
+    if url.is_empty() || has_scheme || url.starts_with("//") {
+        url.to_string()
+    } else if url.starts_with('/') {
+        site.absolute_url(url)
+    } else if url.starts_with('#') {
+        format!("{}{url}", site.absolute_url(&page.url))
+    } else {
+        let folder = page.url.rsplit_once('/').map_or("", |(folder, _)| folder);
+        site.absolute_url(&format!("{folder}/{url}"))
+    }
+}
+
+pub fn escape_xml(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_xml_test() {
+        let output = "&lt;p&gt;Cats &amp; &quot;dogs&quot;&lt;/p&gt;";
+        assert_eq!(escape_xml("<p>Cats & \"dogs\"</p>"), output);
+    }
+
+    #[test]
+    fn create_rss_test() {
+        let mut site: Site = Site::default();
+        let mut page: Page = Page::new();
+        page.title = String::from("A Post");
+        page.description = String::from("About things");
+        page.category = String::from("blog");
+        page.filename = String::from("a-post");
+        page.date = String::from("2021-05-08T15:16:19-07:00");
+        page.url = String::from("/blog/a-post.html");
+        page.html = String::from("<p>Hello</p>\n");
+        site.pages.push(page);
+
+        let pages = site.feed_pages(None);
+        let output = create_rss(&site, &pages, "md_puppy site", "/rss.xml");
+        assert!(output.contains("<link>https://www.example.com/blog/a-post.html</link>"));
+        assert!(output.contains("<pubDate>Sat, 08 May 2021 15:16:19 -0700</pubDate>"));
+        assert!(!output.contains("<content:encoded>"));
+
+        site.feeds.full_content = true;
+        let pages = site.feed_pages(None);
+        let output = create_rss(&site, &pages, "md_puppy site", "/rss.xml");
+        assert!(output.contains("<content:encoded>&lt;p&gt;Hello&lt;/p&gt;\n</content:encoded>"));
+
+        let output = create_atom(&site, &pages, "md_puppy site", "/atom.xml");
+        assert!(output.contains("<link href=\"https://www.example.com/atom.xml\" rel=\"self\""));
+        assert!(output.contains("<updated>2021-05-08T15:16:19-07:00</updated>"));
+
+        let output = create_json_feed(&site, &pages, "md_puppy site", "/feed.json");
+        let feed: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(
+            feed["items"][0]["url"],
+            "https://www.example.com/blog/a-post.html"
+        );
+        assert_eq!(feed["items"][0]["content_html"], "<p>Hello</p>\n");
+    }
+
+    #[test]
+    fn feed_html_test() {
+        let site: Site = Site::default();
+        let mut page: Page = Page::new();
+        page.url = String::from("/blog/a-post.html");
+        page.html = String::from(
+            "<a href=\"/about.html\">a</a> <img src=\"cat.png\" data-src=\"x.png\"> <a href=\"#top\">b</a> <a href='https://a.org/'>c</a> <a href=\"mailto:me@a.org\">d</a>",
+        );
+
+        assert_eq!(
+            feed_html(&site, &page),
+            "<a href=\"https://www.example.com/about.html\">a</a> <img src=\"https://www.example.com/blog/cat.png\" data-src=\"x.png\"> <a href=\"https://www.example.com/blog/a-post.html#top\">b</a> <a href='https://a.org/'>c</a> <a href=\"mailto:me@a.org\">d</a>"
+        );
+    }
+
+    #[test]
+    fn undated_atom_entry_test() {
+        let site: Site = Site::default();
+        let mut page: Page = Page::new();
+        page.date = String::new();
+        page.filepath = String::from("content/missing.md");
+
+        let entry = create_atom_entry(&site, &page);
+        assert!(!entry.contains("<published></published>"));
+        assert!(!entry.contains("<updated></updated>"));
+        assert!(parse_date(&get_atom_date(&page).to_rfc3339()).is_some());
+    }
+
+    #[test]
+    fn create_feed_links_test() {
+        let mut site: Site = Site::default();
+        site.feeds.formats = vec![FeedFormat::Rss, FeedFormat::Json];
+        site.feeds.per_category = true;
+        site.categories.insert(String::from("blog"));
+
+        let output = "\
+<link rel=\"alternate\" type=\"application/rss+xml\" title=\"md_puppy site\" href=\"/rss.xml\">
+<link rel=\"alternate\" type=\"application/feed+json\" title=\"md_puppy site\" href=\"/feed.json\">
+<link rel=\"alternate\" type=\"application/rss+xml\" title=\"md_puppy site - blog\" href=\"/blog/rss.xml\">
+<link rel=\"alternate\" type=\"application/feed+json\" title=\"md_puppy site - blog\" href=\"/blog/feed.json\">
+";
+        assert_eq!(create_feed_links(&site, "blog"), output);
+
+        site.feeds.enabled = false;
+        assert_eq!(create_feed_links(&site, "blog"), "");
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+
+use pulldown_cmark::{CowStr, Event, Tag};
+use yaml_rust::Yaml;
+
+use crate::markdown_compiling::slugify;
+
+/// Options read from the 'toc' section of the config
+#[derive(Clone, Debug)]
+pub struct TocConfig {
+    pub min_level: usize,
+    pub max_level: usize,
+}
+
+impl Default for TocConfig {
+    fn default() -> TocConfig {
+        TocConfig {
+            min_level: 2,
+            max_level: 3,
+        }
+    }
+}
+
+impl TocConfig {
+    pub fn from_yaml(yaml: &Yaml) -> TocConfig {
+        let default = TocConfig::default();
+        let get_level = |key: &str, default: usize| -> usize {
+            yaml[key]
+                .as_i64()
+                .map_or(default, |level| level.clamp(1, 6) as usize)
+        };
+
+        TocConfig {
+            min_level: get_level("min_level", default.min_level),
+            max_level: get_level("max_level", default.max_level),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heading {
+    pub level: usize,
+    pub id: String,
+    pub text: String,
+}
+
+/// A heading whose closing tag hasn't been reached yet
+struct OpenHeading<'a> {
+    level: usize,
+    id: Option<String>,
+    classes: Vec<String>,
+    inner: Vec<Event<'a>>,
+}
+
+/// Gives every heading an id made from its text, unless it sets its own with '{#custom-id}',
+/// adding a number to the end of any repeated ids so each one is unique within the page, with a
+/// warning for repeated custom ids. If `anchors` is set a '#' link to the heading is added after
+/// its text. Returns the headings found so they can be used in a table of contents.
+pub fn add_heading_ids(events: Vec<Event>, anchors: bool) -> (Vec<Event>, Vec<Heading>) {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut headings: Vec<Heading> = Vec::new();
+    let mut current: Option<OpenHeading> = None;
+
+    // Custom ids are kept as they are, so generated ids have to avoid them
+    let mut used_ids: HashSet<String> = HashSet::new();
+    for event in &events {
+        if let Event::Start(Tag::Heading(_, Some(id), _)) = event {
+            used_ids.insert(id.to_string());
+        }
+    }
+    let mut custom_ids: HashSet<String> = HashSet::new();
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level, id, classes)) => {
+                current = Some(OpenHeading {
+                    level: level as usize,
+                    id: id.map(String::from),
+                    classes: classes.iter().map(|c| c.to_string()).collect(),
+                    inner: Vec::new(),
+                });
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some(OpenHeading {
+                    level,
+                    id,
+                    classes,
+                    inner,
+                }) = current.take()
+                {
+                    let text = get_heading_text(&inner);
+                    let id = match id {
+                        Some(id) if custom_ids.insert(id.to_string()) => id,
+                        Some(id) => {
+                            println!("[ WARN ] The heading id '{id}' is used more than once, giving '{text}' a numbered id");
+                            get_unique_id(&id, &mut used_ids)
+                        }
+                        None => get_unique_id(&slugify(&text), &mut used_ids),
+                    };
+                    let escaped_id = escape_html(&id);
+
+                    let class = match classes.is_empty() {
+                        true => String::new(),
+                        false => format!(" class=\"{}\"", classes.join(" ")),
+                    };
+                    output.push(Event::Html(CowStr::from(format!(
+                        "<h{level} id=\"{escaped_id}\"{class}>"
+                    ))));
+                    output.extend(inner);
+                    if anchors {
+                        output.push(Event::Html(CowStr::from(format!(
+                            " <a class=\"heading-anchor\" href=\"#{escaped_id}\" aria-label=\"Link to this section\">#</a>"
+                        ))));
+                    }
+                    output.push(Event::Html(CowStr::from(format!("</h{level}>\n"))));
+
+                    headings.push(Heading { level, id, text });
+                }
+            }
+            event => match current.as_mut() {
+                Some(heading) => heading.inner.push(event),
+                None => output.push(event),
+            },
+        }
+    }
+
+    (output, headings)
+}
+
+fn get_heading_text(events: &[Event]) -> String {
+    let mut text: String = String::new();
+
+    for event in events {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => (),
+        }
+    }
+
+    text.trim().to_string()
+}
+
+/// Adds a number to the end of an id if it is already used, like 'setup-1'.
+fn get_unique_id(id: &str, used_ids: &mut HashSet<String>) -> String {
+    let id = match id.is_empty() {
+        true => "section",
+        false => id,
+    };
+
+    let mut unique_id = id.to_string();
+    let mut count: usize = 0;
+    while used_ids.contains(&unique_id) {
+        count += 1;
+        unique_id = format!("{id}-{count}");
+    }
+    used_ids.insert(unique_id.to_string());
+
+    unique_id
+}
+
+/// Creates a nested list of links to every heading between the min and max level.
+pub fn create_toc(headings: &[Heading], config: &TocConfig) -> String {
+    let mut output: String = String::new();
+    let mut levels: Vec<usize> = Vec::new();
+
+    for heading in headings
+        .iter()
+        .filter(|h| h.level >= config.min_level && h.level <= config.max_level)
+    {
+        while levels.len() > 1 && heading.level < *levels.last().unwrap() {
+            output.push_str("</li>\n</ul>\n");
+            levels.pop();
+        }
+
+        match levels.last() {
+            Some(&level) if heading.level > level => {
+                output.push_str("\n<ul>\n<li>");
+                levels.push(heading.level);
+            }
+            Some(_) => output.push_str("</li>\n<li>"),
+            None => {
+                output.push_str("<ul class=\"toc\">\n<li>");
+                levels.push(heading.level);
+            }
+        }
+
+        output.push_str(&format!(
+            "<a href=\"#{}\">{}</a>",
+            escape_html(&heading.id),
+            escape_html(&heading.text)
+        ));
+    }
+
+    for _ in levels {
+        output.push_str("</li>\n</ul>\n");
+    }
+
+    output
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Options, Parser};
+
+    fn render(input: &str, anchors: bool) -> (String, Vec<Heading>) {
+        let parser = Parser::new_ext(input, Options::ENABLE_HEADING_ATTRIBUTES);
+        let (events, headings) = add_heading_ids(parser.collect(), anchors);
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+        (output, headings)
+    }
+
+    #[test]
+    fn add_heading_ids_test() {
+        let input = "# Getting Started!\n\n## Install `md_puppy`\n\n## Getting Started\n";
+        let (output, headings) = render(input, false);
+
+        let answer = "\
+<h1 id=\"getting-started\">Getting Started!</h1>
+<h2 id=\"install-md-puppy\">Install <code>md_puppy</code></h2>
+<h2 id=\"getting-started-1\">Getting Started</h2>
+";
+        assert_eq!(output, answer);
+        assert_eq!(headings[1].text, "Install md_puppy");
+    }
+
+    #[test]
+    fn custom_heading_ids_test() {
+        let input = "## Setup\n\n## Setup again {#setup .big}\n";
+        let (output, _) = render(input, true);
+
+        let answer = "\
+<h2 id=\"setup-1\">Setup <a class=\"heading-anchor\" href=\"#setup-1\" aria-label=\"Link to this section\">#</a></h2>
+<h2 id=\"setup\" class=\"big\">Setup again <a class=\"heading-anchor\" href=\"#setup\" aria-label=\"Link to this section\">#</a></h2>
+";
+        assert_eq!(output, answer);
+    }
+
+    #[test]
+    fn repeated_custom_heading_ids_test() {
+        let input = "## Start {#start}\n\n## Start 1\n\n## Again {#start}\n\n## Last {#start-1}\n";
+        let (_, headings) = render(input, false);
+        let ids: Vec<&str> = headings.iter().map(|h| h.id.as_str()).collect();
+
+        assert_eq!(ids, vec!["start", "start-1-1", "start-2", "start-1"]);
+    }
+
+    #[test]
+    fn create_toc_test() {
+        let heading = |level: usize, id: &str| Heading {
+            level,
+            id: id.to_string(),
+            text: id.to_string(),
+        };
+        let headings = vec![
+            heading(1, "title"),
+            heading(2, "one"),
+            heading(3, "one-a"),
+            heading(3, "one-b"),
+            heading(2, "two"),
+        ];
+
+        let answer = "\
+<ul class=\"toc\">
+<li><a href=\"#one\">one</a>
+<ul>
+<li><a href=\"#one-a\">one-a</a></li>
+<li><a href=\"#one-b\">one-b</a></li>
+</ul>
+</li>
+<li><a href=\"#two\">two</a></li>
+</ul>
+";
+        assert_eq!(create_toc(&headings, &TocConfig::default()), answer);
+        assert_eq!(create_toc(&[], &TocConfig::default()), "");
+    }
+}
@@ -0,0 +1,385 @@
+use anyhow::Result;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+    css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html,
+    ClassStyle, IncludeBackground,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use yaml_rust::Yaml;
+
+use crate::directory_handling::write_site_file;
+use crate::site_data::Site;
+
+const DEFAULT_THEME: &str = "InspiredGitHub";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+pub const HIGHLIGHT_CSS_PATH: &str = "/css/highlight.css";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HighlightStyle {
+    /// Wraps code in classes, with the colours written to 'site/css/highlight.css'
+    Classes,
+    /// Puts the colours straight into each element's 'style' attribute
+    Inline,
+}
+
+/// Options read from the 'highlight' section of the config, along with the syntaxes and theme
+/// used to highlight code blocks.
+#[derive(Clone, Debug)]
+pub struct Highlighter {
+    pub style: HighlightStyle,
+    pub line_numbers: bool,
+    syntax_set: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new(style: HighlightStyle, theme: &str) -> Highlighter {
+        Highlighter {
+            style,
+            line_numbers: false,
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme: load_theme(theme),
+        }
+    }
+
+    pub fn from_yaml(yaml: &Yaml) -> Option<Highlighter> {
+        let enabled = match yaml.as_bool() {
+            Some(enabled) => enabled,
+            None => yaml.as_hash().is_some() && yaml["enabled"].as_bool().unwrap_or(true),
+        };
+        if !enabled {
+            return None;
+        }
+
+        let style = match yaml["style"].as_str() {
+            Some("inline") => HighlightStyle::Inline,
+            Some("classes") | None => HighlightStyle::Classes,
+            Some(style) => {
+                println!("[ WARN ] Unknown highlight style {style}, use classes or inline");
+                HighlightStyle::Classes
+            }
+        };
+
+        Some(Highlighter {
+            line_numbers: yaml["line_numbers"].as_bool().unwrap_or(false),
+            ..Highlighter::new(style, yaml["theme"].as_str().unwrap_or(DEFAULT_THEME))
+        })
+    }
+
+    /// Replaces every fenced code block with highlighted HTML, keeping the text of any other
+    /// events as it is.
+    pub fn highlight_code_blocks<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        let mut output: Vec<Event> = Vec::with_capacity(events.len());
+        let mut current: Option<(String, String)> = None;
+
+        for event in events {
+            match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    current = Some((info.to_string(), String::new()));
+                }
+                Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => {
+                    if let Some((info, code)) = current.take() {
+                        let html = self.highlight_block(&info, &code);
+                        output.push(Event::Html(CowStr::from(html)));
+                    }
+                }
+                Event::Text(text) if current.is_some() => {
+                    if let Some((_, code)) = current.as_mut() {
+                        code.push_str(&text);
+                    }
+                }
+                event => output.push(event),
+            }
+        }
+
+        output
+    }
+
+    /// Highlights a code block whose info string is like 'rust {3,5-7}', where the numbers in
+    /// braces are the lines to mark as highlighted.
+    fn highlight_block(&self, info: &str, code: &str) -> String {
+        let (language, marked_lines) = parse_info_string(info);
+        let syntax: &SyntaxReference = self
+            .syntax_set
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+
+        let lines: Vec<String> = match self.style {
+            HighlightStyle::Classes => self.highlight_with_classes(code, syntax),
+            HighlightStyle::Inline => self.highlight_inline(code, syntax),
+        }
+        .unwrap_or_else(|_| {
+            println!("[ WARN ] Could not highlight {language} code block, leaving it plain");
+            LinesWithEndings::from(code).map(escape_html).collect()
+        });
+
+        let mut output: String = match self.style {
+            HighlightStyle::Classes => String::from("<pre class=\"highlight hl-code\">"),
+            HighlightStyle::Inline => format!(
+                "<pre class=\"highlight\" style=\"background-color:{};\">",
+                css_color(self.theme.settings.background, "#ffffff")
+            ),
+        };
+        match language.is_empty() {
+            true => output.push_str("<code>"),
+            false => output.push_str(&format!("<code class=\"language-{language}\">")),
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            let number = i + 1;
+            let marked = marked_lines
+                .iter()
+                .any(|(start, end)| number >= *start && number <= *end);
+
+            output.push_str(&match (marked, self.style) {
+                (false, _) => String::from("<span class=\"line\">"),
+                (true, HighlightStyle::Classes) => {
+                    String::from("<span class=\"line highlighted\">")
+                }
+                (true, HighlightStyle::Inline) => format!(
+                    "<span class=\"line highlighted\" style=\"display:inline-block;width:100%;background-color:{};\">",
+                    css_color(self.theme.settings.line_highlight, "#fff8c5")
+                ),
+            });
+            if self.line_numbers {
+                output.push_str(&match self.style {
+                    HighlightStyle::Classes => format!("<span class=\"line-number\">{number}</span>"),
+                    HighlightStyle::Inline => format!(
+                        "<span class=\"line-number\" style=\"display:inline-block;min-width:2em;margin-right:1em;text-align:right;opacity:0.5;user-select:none;\">{number}</span>"
+                    ),
+                });
+            }
+            output.push_str(&line.replace('\n', ""));
+            output.push_str("</span>\n");
+        }
+
+        output.push_str("</code></pre>\n");
+        output
+    }
+
+    fn highlight_with_classes(
+        &self,
+        code: &str,
+        syntax: &SyntaxReference,
+    ) -> Result<Vec<String>, syntect::Error> {
+        let mut parse_state = ParseState::new(syntax);
+        let mut scope_stack = ScopeStack::new();
+        let mut open_spans: Vec<String> = Vec::new();
+        let mut lines: Vec<String> = Vec::new();
+
+        for line in LinesWithEndings::from(code) {
+            let ops = parse_state.parse_line(line, &self.syntax_set)?;
+            let (html, _) =
+                line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scope_stack)?;
+            lines.push(balance_spans(&html, &mut open_spans));
+        }
+
+        Ok(lines)
+    }
+
+    fn highlight_inline(
+        &self,
+        code: &str,
+        syntax: &SyntaxReference,
+    ) -> Result<Vec<String>, syntect::Error> {
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut lines: Vec<String> = Vec::new();
+
+        for line in LinesWithEndings::from(code) {
+            let regions = highlighter.highlight_line(line, &self.syntax_set)?;
+            lines.push(styled_line_to_highlighted_html(
+                &regions,
+                IncludeBackground::No,
+            )?);
+        }
+
+        Ok(lines)
+    }
+
+    /// Creates the stylesheet used by class based highlighting from the theme.
+    pub fn create_css(&self) -> String {
+        let mut css: String =
+            css_for_theme_with_class_style(&self.theme, CLASS_STYLE).unwrap_or_default();
+
+        css.push_str(&format!(
+            "
+.highlight .line.highlighted {{
+  display: inline-block;
+  width: 100%;
+  background-color: {};
+}}
+.highlight .line-number {{
+  display: inline-block;
+  min-width: 2em;
+  margin-right: 1em;
+  text-align: right;
+  opacity: 0.5;
+  user-select: none;
+}}
+",
+            css_color(self.theme.settings.line_highlight, "#fff8c5")
+        ));
+
+        css
+    }
+}
+
+/// Writes the stylesheet for class based highlighting to 'site/css/highlight.css'.
+pub fn write_highlight_css(site: &Site) -> Result<()> {
+    match &site.highlight {
+        Some(highlighter) if highlighter.style == HighlightStyle::Classes => write_site_file(
+            &format!("site{HIGHLIGHT_CSS_PATH}"),
+            &highlighter.create_css(),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn load_theme(name: &str) -> Theme {
+    if name.ends_with(".tmTheme") {
+        match ThemeSet::get_theme(name) {
+            Ok(theme) => return theme,
+            Err(_) => {
+                println!("[ WARN ] Could not load highlight theme {name}, using {DEFAULT_THEME}")
+            }
+        }
+    }
+
+    let mut themes = ThemeSet::load_defaults().themes;
+    match themes.remove(name) {
+        Some(theme) => theme,
+        None => {
+            if !name.ends_with(".tmTheme") {
+                let names: Vec<&String> = themes.keys().collect();
+                println!("[ WARN ] Unknown highlight theme {name}, using {DEFAULT_THEME}. Themes are {names:?}");
+            }
+            themes.remove(DEFAULT_THEME).unwrap_or_default()
+        }
+    }
+}
+
+/// Splits an info string like 'rust {3,5-7}' into the language and the ranges of lines to mark.
+fn parse_info_string(info: &str) -> (&str, Vec<(usize, usize)>) {
+    let (language, lines) = match (info.find('{'), info.rfind('}')) {
+        (Some(start), Some(end)) if start < end => (&info[..start], &info[start + 1..end]),
+        _ => (info, ""),
+    };
+    // The language is used as a class, so it stops at anything which could escape the attribute
+    let language = language
+        .trim_start()
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'))
+        .next()
+        .unwrap_or("");
+
+    let ranges = lines
+        .split(',')
+        .filter_map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            match (start.trim().parse(), end.trim().parse()) {
+                (Ok(start), Ok(end)) => Some((start, end)),
+                _ => None,
+            }
+        })
+        .collect();
+
+    (language, ranges)
+}
+
+/// Closes any spans left open at the end of a line, and reopens the ones from the line before,
+/// so each line can be wrapped in its own element.
+fn balance_spans(html: &str, open_spans: &mut Vec<String>) -> String {
+    let mut output: String = open_spans.concat();
+    output.push_str(html);
+
+    let mut rest: &str = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+        match &rest[..end] {
+            "</span>" => {
+                open_spans.pop();
+            }
+            tag if tag.starts_with("<span") => open_spans.push(tag.to_string()),
+            _ => (),
+        }
+        rest = &rest[end..];
+    }
+
+    output.push_str(&"</span>".repeat(open_spans.len()));
+    output
+}
+
+fn css_color(color: Option<Color>, default: &str) -> String {
+    match color {
+        Some(Color { r, g, b, .. }) => format!("#{r:02x}{g:02x}{b:02x}"),
+        None => default.to_string(),
+    }
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(input: &str, highlighter: &Highlighter) -> String {
+        let events = highlighter.highlight_code_blocks(Parser::new(input).collect());
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+        output
+    }
+
+    #[test]
+    fn parse_info_string_test() {
+        assert_eq!(parse_info_string("rust"), ("rust", vec![]));
+        assert_eq!(
+            parse_info_string("rust {3,5-7}"),
+            ("rust", vec![(3, 3), (5, 7)])
+        );
+        assert_eq!(parse_info_string("{2}"), ("", vec![(2, 2)]));
+        assert_eq!(parse_info_string("a\"onmouseover=alert(1)"), ("a", vec![]));
+    }
+
+    #[test]
+    fn balance_spans_test() {
+        let mut open_spans: Vec<String> = Vec::new();
+        let first = balance_spans("<span class=\"a\">/* one", &mut open_spans);
+        let second = balance_spans("two */</span>", &mut open_spans);
+
+        assert_eq!(first, "<span class=\"a\">/* one</span>");
+        assert_eq!(second, "<span class=\"a\">two */</span>");
+        assert!(open_spans.is_empty());
+    }
+
+    #[test]
+    fn highlight_code_blocks_test() {
+        let mut highlighter = Highlighter::new(HighlightStyle::Classes, DEFAULT_THEME);
+        highlighter.line_numbers = true;
+        let input = "```rust {2}\nlet a = 1;\nlet b = 2;\n```\n";
+        let output = render(input, &highlighter);
+
+        assert!(
+            output.starts_with("<pre class=\"highlight hl-code\"><code class=\"language-rust\">")
+        );
+        assert!(output.contains("<span class=\"line\"><span class=\"line-number\">1</span>"));
+        assert!(output
+            .contains("<span class=\"line highlighted\"><span class=\"line-number\">2</span>"));
+        assert!(output.contains("<span class=\"hl-keyword"));
+        assert_eq!(output.matches("class=\"line-number\"").count(), 2);
+
+        let highlighter = Highlighter::new(HighlightStyle::Inline, DEFAULT_THEME);
+        let output = render("```\na < b\n```\n", &highlighter);
+        assert!(output.contains("style=\"color:"));
+        assert!(output.contains("a &lt; b"));
+        assert!(!output.contains("hl-"));
+    }
+}
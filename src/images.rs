@@ -0,0 +1,284 @@
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::escape_href;
+use pulldown_cmark::{CowStr, Event, Tag};
+use yaml_rust::Yaml;
+
+use crate::links::{decode_path, get_scheme};
+use crate::markdown_compiling::Page;
+use crate::site_data::Site;
+
+/// How images are written, read from the 'images' section of the config
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageConfig {
+    /// Place images with a title on their own line in a '<figure>' with the title as its caption
+    pub figures: bool,
+    /// Add 'loading="lazy"' and 'decoding="async"' so images load as they are scrolled to
+    pub lazy_loading: bool,
+    /// Add the width and height of images found in 'static/' or the page's folder
+    pub dimensions: bool,
+}
+
+impl Default for ImageConfig {
+    fn default() -> ImageConfig {
+        ImageConfig {
+            figures: true,
+            lazy_loading: true,
+            dimensions: true,
+        }
+    }
+}
+
+impl ImageConfig {
+    pub fn from_yaml(yaml: &Yaml) -> ImageConfig {
+        let default = ImageConfig::default();
+
+        ImageConfig {
+            figures: yaml["figures"].as_bool().unwrap_or(default.figures),
+            lazy_loading: yaml["lazy_loading"]
+                .as_bool()
+                .unwrap_or(default.lazy_loading),
+            dimensions: yaml["dimensions"].as_bool().unwrap_or(default.dimensions),
+        }
+    }
+}
+
+/// Writes each image as html with the attributes from the 'images' config, turning paragraphs
+/// holding only an image with a title, like '![alt](a.png "Caption")', into a '<figure>'.
+pub fn render_images<'a>(events: Vec<Event<'a>>, page: &Page, site: &Site) -> Vec<Event<'a>> {
+    let config = &site.images;
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut i: usize = 0;
+
+    while i < events.len() {
+        let (dest, title) = match &events[i] {
+            Event::Start(Tag::Image(_, dest, title)) => (dest, title),
+            event => {
+                output.push(event.clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        let end = find_image_end(&events, i);
+        let alt = get_alt_text(&events[i + 1..end]);
+        let is_alone = i > 0
+            && events[i - 1] == Event::Start(Tag::Paragraph)
+            && events.get(end + 1) == Some(&Event::End(Tag::Paragraph));
+
+        if config.figures && is_alone && !title.is_empty() {
+            let img = create_img(dest, &alt, "", page, site);
+            // The figure replaces the paragraph the image was in
+            output.pop();
+            output.push(Event::Html(CowStr::from(format!(
+                "<figure>\n{img}\n<figcaption>{}</figcaption>\n</figure>\n",
+                escape_html(title)
+            ))));
+            i = end + 2;
+        } else {
+            output.push(Event::Html(CowStr::from(create_img(
+                dest, &alt, title, page, site,
+            ))));
+            i = end + 1;
+        }
+    }
+
+    output
+}
+
+fn create_img(dest: &str, alt: &str, title: &str, page: &Page, site: &Site) -> String {
+    let mut src: String = String::new();
+    // Writing to a String can't fail
+    escape_href(&mut src, &get_image_src(dest, page)).ok();
+
+    let mut html = format!("<img src=\"{src}\" alt=\"{}\"", escape_html(alt));
+    if !title.is_empty() {
+        html.push_str(&format!(" title=\"{}\"", escape_html(title)));
+    }
+    if site.images.dimensions {
+        if let Some((width, height)) = find_image_size(dest, page, site) {
+            html.push_str(&format!(" width=\"{width}\" height=\"{height}\""));
+        }
+    }
+    if site.images.lazy_loading {
+        html.push_str(" loading=\"lazy\" decoding=\"async\"");
+    }
+    html.push_str(" />");
+
+    html
+}
+
+/// Writes relative image paths from the root of the site, starting from the folder the page is
+/// written to. Pages have a '<base>' tag pointing at the root of the site, so a relative path
+/// would otherwise miss images copied next to the page.
+fn get_image_src(dest: &str, page: &Page) -> String {
+    let is_relative =
+        !dest.is_empty() && !dest.starts_with(['/', '#', '?']) && get_scheme(dest).is_none();
+    if !is_relative {
+        return dest.to_string();
+    }
+
+    let output_dir = page.url.rsplit_once('/').map_or("", |(dir, _)| dir);
+    format!("{output_dir}/{}", dest.trim_start_matches("./"))
+}
+
+/// Gets the index of the event which ends the image starting at `start`.
+fn find_image_end(events: &[Event], start: usize) -> usize {
+    let mut depth: usize = 0;
+
+    for (i, event) in events.iter().enumerate().skip(start) {
+        match event {
+            Event::Start(Tag::Image(..)) => depth += 1,
+            Event::End(Tag::Image(..)) => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    events.len() - 1
+}
+
+/// Gets the alt text of an image from the events between its start and end, without formatting.
+fn get_alt_text(events: &[Event]) -> String {
+    let mut alt: String = String::new();
+
+    for event in events {
+        match event {
+            Event::Text(text) | Event::Code(text) => alt.push_str(text),
+            Event::SoftBreak | Event::HardBreak => alt.push(' '),
+            _ => (),
+        }
+    }
+
+    alt
+}
+
+/// Reads the width and height of an image on the site from the files it is built from.
+fn find_image_size(dest: &str, page: &Page, site: &Site) -> Option<(usize, usize)> {
+    image_paths(dest, page, site)
+        .into_iter()
+        .find_map(|path| imagesize::size(path).ok())
+        .map(|size| (size.width, size.height))
+}
+
+/// Gets the files an image could be read from, which is 'static/' for paths from the root of the
+/// site, or the folder the page is written to in 'site/' for relative paths, which holds what was
+/// copied there from 'static/' and the page's folder in 'content/'. Images on other sites have no
+/// paths.
+fn image_paths(dest: &str, page: &Page, site: &Site) -> Vec<PathBuf> {
+    let dest = match site.base_url.to_site_path(dest) {
+        Some(path) => path,
+        None if get_scheme(dest).is_some() || dest.starts_with("//") => return Vec::new(),
+        None => dest.to_string(),
+    };
+    let path = decode_path(dest.split(['?', '#']).next().unwrap_or(""));
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    match path.strip_prefix('/') {
+        Some(path) => vec![Path::new("static").join(path)],
+        None => {
+            let output_dir = page.url.rsplit_once('/').map_or("", |(dir, _)| dir);
+            vec![Path::new("site")
+                .join(output_dir.trim_start_matches('/'))
+                .join(&path)]
+        }
+    }
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(input: &str, site: &Site) -> String {
+        let events = render_images(Parser::new(input).collect(), &Page::new(), site);
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+        output
+    }
+
+    #[test]
+    fn render_images_test() {
+        let site = Site {
+            images: ImageConfig {
+                dimensions: false,
+                ..ImageConfig::default()
+            },
+            ..Site::default()
+        };
+
+        assert_eq!(
+            render("![A *small* cat](/img/cat.png \"Our cat\")\n", &site),
+            "<figure>\n<img src=\"/img/cat.png\" alt=\"A small cat\" loading=\"lazy\" decoding=\"async\" />\n<figcaption>Our cat</figcaption>\n</figure>\n"
+        );
+        assert_eq!(
+            render("Inline ![cat](cat.png \"Our cat\") image\n", &site),
+            "<p>Inline <img src=\"/cat.png\" alt=\"cat\" title=\"Our cat\" loading=\"lazy\" decoding=\"async\" /> image</p>\n"
+        );
+
+        let site = Site {
+            images: ImageConfig {
+                figures: false,
+                lazy_loading: false,
+                dimensions: false,
+            },
+            ..Site::default()
+        };
+        assert_eq!(
+            render("![cat](cat.png \"Our cat\")\n", &site),
+            "<p><img src=\"/cat.png\" alt=\"cat\" title=\"Our cat\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn get_image_src_test() {
+        let mut page = Page::new();
+        page.url = String::from("/2021/05/trip/");
+
+        assert_eq!(get_image_src("beach.png", &page), "/2021/05/trip/beach.png");
+        assert_eq!(get_image_src("./a/b.png", &page), "/2021/05/trip/a/b.png");
+        assert_eq!(get_image_src("/img/a.png", &page), "/img/a.png");
+        assert_eq!(
+            get_image_src("https://github.com/a.png", &page),
+            "https://github.com/a.png"
+        );
+        page.url = String::from("/index.html");
+        assert_eq!(get_image_src("beach.png", &page), "/beach.png");
+    }
+
+    #[test]
+    fn image_paths_test() {
+        let site = Site::default();
+        let mut page = Page::new();
+        page.filepath = String::from("content/posts/trip.md");
+        page.url = String::from("/blog/trip.html");
+
+        assert_eq!(
+            image_paths("/img/a%20b.png?v=2", &page, &site),
+            vec![PathBuf::from("static/img/a b.png")]
+        );
+        assert_eq!(
+            image_paths("photo.jpg", &page, &site),
+            vec![PathBuf::from("site/blog/photo.jpg")]
+        );
+        assert_eq!(
+            image_paths("https://www.example.com/img/a.png", &page, &site),
+            vec![PathBuf::from("static/img/a.png")]
+        );
+        assert!(image_paths("https://github.com/a.png", &page, &site).is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{anyhow, Result};
+use fs_extra::dir::get_dir_content;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::directory_handling::{get_content_files, NOT_FOUND_PATH};
+use crate::links::{decode_path, get_scheme};
+use crate::permalinks::get_output_file;
+use crate::site_data::Site;
+
+/// What to do with links to other sites
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExternalLinks {
+    Ignore,
+    /// Print every external link along with where it is from
+    List,
+    /// Request every external link to see if it still works
+    Check,
+}
+
+/// A link found in a page in 'site/'
+#[derive(Clone, Debug, PartialEq)]
+struct Link {
+    /// The page in 'site/' the link is in
+    page: String,
+    /// The link as written in the page
+    url: String,
+}
+
+/// Checks the links in the built site from the 'check-links' command, returning an error if any
+/// of them are broken.
+pub fn check_site_links(external: ExternalLinks) -> Result<()> {
+    let mut site: Site = Site::new()?;
+    for file in get_content_files()?.pages {
+        site.add_page(&file)?;
+    }
+
+    match check_links(&site, external)? {
+        0 => {
+            println!("[ INFO ] No broken links found");
+            Ok(())
+        }
+        broken => Err(anyhow!("[ ERROR ] Found {broken} broken links")),
+    }
+}
+
+/// Checks that every link and image in the HTML pages in 'site/' points to a file which exists,
+/// and that any '#fragment' matches an id in that page. Each broken link is printed along with
+/// the markdown file, and line, it came from. Returns the number of broken links.
+pub fn check_links(site: &Site, external: ExternalLinks) -> Result<usize> {
+    let files: HashSet<String> = get_dir_content("site/")?
+        .files
+        .iter()
+        .map(|file| file.replace('\\', "/"))
+        .collect();
+    let mut pages: Vec<&String> = files.iter().filter(|f| f.ends_with(".html")).collect();
+    pages.sort();
+
+    let mut ids: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut external_links: Vec<Link> = Vec::new();
+    let mut broken: usize = 0;
+
+    for page in pages {
+        let html = fs::read_to_string(page)?;
+        let base = get_base_path(&html, page, site);
+
+        for url in get_attributes(&html, "href")
+            .into_iter()
+            .chain(get_attributes(&html, "src"))
+        {
+            let link = Link {
+                page: page.to_string(),
+                url: url.replace("&amp;", "&"),
+            };
+            if is_external(&link.url, site) {
+                external_links.push(link);
+                continue;
+            }
+            // Links like 'mailto:' and 'tel:' can't be checked
+            if get_scheme(&link.url).is_some() && site.base_url.to_site_path(&link.url).is_none() {
+                continue;
+            }
+
+            if let Some(problem) = check_internal_link(&link, &base, site, &files, &mut ids) {
+                println!(
+                    "[ WARN ] {} links to {}, {problem}",
+                    find_source(&link, site),
+                    link.url
+                );
+                broken += 1;
+            }
+        }
+    }
+
+    match external {
+        ExternalLinks::Ignore => (),
+        ExternalLinks::List => {
+            for link in &external_links {
+                println!("[ INFO ] {} links to {}", find_source(link, site), link.url);
+            }
+        }
+        ExternalLinks::Check => broken += check_external_links(&external_links, site),
+    }
+
+    Ok(broken)
+}
+
+/// Returns what is wrong with a link to another page on the site, if anything.
+fn check_internal_link(
+    link: &Link,
+    base: &str,
+    site: &Site,
+    files: &HashSet<String>,
+    ids: &mut HashMap<String, HashSet<String>>,
+) -> Option<String> {
+    let url = match site.base_url.to_site_path(&link.url) {
+        Some(path) => path,
+        None => link.url.to_string(),
+    };
+    let (path, fragment) = match url.split_once('#') {
+        Some((path, fragment)) => (path, decode_path(fragment)),
+        None => (url.as_str(), String::new()),
+    };
+    let path = path.split('?').next().unwrap_or("");
+
+    let target: String = match path.is_empty() {
+        true => link.page.to_string(),
+        false => {
+            let file = resolve_url(base, &decode_path(path));
+            let index = format!("{}/index.html", file.trim_end_matches('/'));
+            if files.contains(&file) {
+                file
+            } else if files.contains(&index) {
+                index
+            } else {
+                return Some(String::from("which doesn't exist"));
+            }
+        }
+    };
+
+    if fragment.is_empty() || !target.ends_with(".html") {
+        return None;
+    }
+    let target_ids = ids.entry(target.to_string()).or_insert_with(|| {
+        let html = fs::read_to_string(&target).unwrap_or_default();
+        get_attributes(&html, "id")
+            .into_iter()
+            .chain(get_attributes(&html, "name"))
+            .map(String::from)
+            .collect()
+    });
+    match target_ids.contains(&fragment) {
+        true => None,
+        false => Some(format!(
+            "which has no heading or element with the id '{fragment}'"
+        )),
+    }
+}
+
+/// Requests each external link once, returning the number which didn't work.
+fn check_external_links(links: &[Link], site: &Site) -> usize {
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .user_agent("md_puppy link checker")
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[ WARN ] Could not check external links: {e}");
+            return 0;
+        }
+    };
+
+    let mut results: HashMap<String, Option<String>> = HashMap::new();
+    let mut broken: usize = 0;
+
+    for link in links {
+        let problem = results.entry(link.url.to_string()).or_insert_with(|| {
+            println!("[ INFO ] Checking {}", link.url);
+            // Some servers don't support HEAD requests, so GET is tried as well
+            let response = match client.head(&link.url).send() {
+                Ok(response) if response.status().is_success() => Ok(response),
+                _ => client.get(&link.url).send(),
+            };
+            match response {
+                Ok(response) if response.status().is_success() => None,
+                Ok(response) => Some(format!("which returned {}", response.status())),
+                Err(e) => Some(format!("which could not be reached: {e}")),
+            }
+        });
+
+        if let Some(problem) = problem {
+            println!(
+                "[ WARN ] {} links to {}, {problem}",
+                find_source(link, site),
+                link.url
+            );
+            broken += 1;
+        }
+    }
+
+    broken
+}
+
+/// Checks if a url is outside of the site, which includes other paths on the same host when the
+/// site isn't served from the root of its domain.
+fn is_external(url: &str, site: &Site) -> bool {
+    let is_web_url = matches!(get_scheme(url), Some("http") | Some("https"));
+
+    (is_web_url || url.starts_with("//")) && site.base_url.to_site_path(url).is_none()
+}
+
+/// Gets the folder relative links in a page start from, which is the root of the site when the
+/// page has a '<base>' tag pointing at the site, or the page's own folder otherwise.
+fn get_base_path(html: &str, page: &str, site: &Site) -> String {
+    let base_tag = html
+        .find("<base ")
+        .and_then(|start| get_attributes(&html[start..], "href").into_iter().next());
+
+    match base_tag.and_then(|base| site.base_url.to_site_path(base)) {
+        Some(path) => format!("site{path}"),
+        None => Path::new(page)
+            .parent()
+            .map_or(String::from("site"), |parent| parent.display().to_string()),
+    }
+}
+
+/// Finds the file in 'site/' a URL points to, from the folder `base` or, if it is empty, the
+/// folder of the page the link is in.
+fn resolve_url(base: &str, url: &str) -> String {
+    let mut parts: Vec<&str> = match url.starts_with('/') {
+        true => vec!["site"],
+        false => base.split('/').filter(|p| !p.is_empty()).collect(),
+    };
+
+    for part in url.split('/') {
+        match part {
+            "" | "." => (),
+            ".." if parts.len() > 1 => {
+                parts.pop();
+            }
+            ".." => (),
+            part => parts.push(part),
+        }
+    }
+
+    let mut path = parts.join("/");
+    if url.ends_with('/') {
+        path.push('/');
+    }
+    path
+}
+
+/// Gets the values of every attribute with the given name in some HTML.
+fn get_attributes<'a>(html: &'a str, name: &str) -> Vec<&'a str> {
+    let mut values: Vec<&str> = Vec::new();
+    let pattern = format!("{name}=");
+    let mut rest: &str = html;
+
+    while let Some(start) = rest.find(&pattern) {
+        let before = rest[..start].chars().last();
+        rest = &rest[start + pattern.len()..];
+        if !before.map_or(false, char::is_whitespace) {
+            continue;
+        }
+
+        let quote = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => continue,
+        };
+        if let Some(end) = rest[1..].find(quote) {
+            values.push(&rest[1..end + 1]);
+            rest = &rest[end + 2..];
+        }
+    }
+
+    values
+}
+
+/// Finds where a link was written, as the markdown file and line it is on, or the line of the
+/// template if it isn't in the markdown. Falls back to the page in 'site/'.
+fn find_source(link: &Link, site: &Site) -> String {
+    let source = site
+        .pages
+        .iter()
+        .find(|page| get_output_file(&page.url) == link.page)
+        .map(|page| page.filepath.as_str())
+        .or_else(|| (link.page == "site/404.html").then(|| NOT_FOUND_PATH));
+
+    // Links to other markdown files or pages are often written differently to how they end up,
+    // so the fragment and name of the file are looked for as well
+    let (path, fragment) = link.url.split_once('#').unwrap_or((&link.url, ""));
+    let stem = path
+        .rsplit('/')
+        .next()
+        .unwrap_or("")
+        .split('.')
+        .next()
+        .unwrap_or("");
+    let needles: Vec<&str> = [link.url.as_str(), path, fragment, stem]
+        .into_iter()
+        .filter(|needle| !needle.is_empty())
+        .collect();
+
+    for file in source.iter().chain([&site.template_path.as_str()]) {
+        if let Some(line) = find_line(file, &needles) {
+            return format!("{file}:{line}");
+        }
+    }
+
+    source.unwrap_or(&link.page).to_string()
+}
+
+fn find_line(file: &str, needles: &[&str]) -> Option<usize> {
+    let text = fs::read_to_string(Path::new(file)).ok()?;
+
+    needles.iter().find_map(|needle| {
+        text.lines()
+            .position(|line| line.contains(needle))
+            .map(|i| i + 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_attributes_test() {
+        let html = "<a href=\"/a.html\" data-href=\"x\"><img src='b.png'><h2 id=\"c\">";
+        assert_eq!(get_attributes(html, "href"), vec!["/a.html"]);
+        assert_eq!(get_attributes(html, "src"), vec!["b.png"]);
+        assert_eq!(get_attributes(html, "id"), vec!["c"]);
+    }
+
+    #[test]
+    fn resolve_url_test() {
+        assert_eq!(resolve_url("site/blog", "post.html"), "site/blog/post.html");
+        assert_eq!(resolve_url("site/blog", "../a/"), "site/a/");
+        assert_eq!(resolve_url("site", "/css/main.css"), "site/css/main.css");
+        assert_eq!(resolve_url("site", "../../x.html"), "site/x.html");
+    }
+
+    #[test]
+    fn is_external_test() {
+        let site = Site::default();
+        assert!(is_external("https://github.com/", &site));
+        assert!(is_external("//cdn.example.org/a.js", &site));
+        assert!(!is_external("https://www.example.com/blog/", &site));
+        assert!(!is_external("/blog/post.html", &site));
+        assert!(!is_external("mailto:me@example.com", &site));
+        assert!(!is_external("post.html#a:b", &site));
+    }
+}
@@ -0,0 +1,566 @@
+use anyhow::{anyhow, Result};
+use yaml_rust::Yaml;
+
+use pulldown_cmark::escape::escape_href;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::markdown_compiling::{slugify, Page};
+use crate::site_data::Site;
+
+/// Written straight after a link to another site to leave it as it is
+const INTERNAL_MARKER: &str = "{.internal}";
+
+/// What to do when a link points at a page which doesn't exist
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrokenLinks {
+    Warn,
+    Error,
+}
+
+impl BrokenLinks {
+    pub fn from_yaml(yaml: &Yaml) -> BrokenLinks {
+        match yaml.as_str() {
+            Some("error") => BrokenLinks::Error,
+            Some("warn") | None => BrokenLinks::Warn,
+            Some(other) => {
+                println!("[ WARN ] Unknown broken_links setting {other}, use warn or error");
+                BrokenLinks::Warn
+            }
+        }
+    }
+
+    /// Warns about a broken link, or returns an error if broken links aren't allowed.
+    pub fn report(&self, message: &str, filepath: &str) -> Result<()> {
+        match self {
+            BrokenLinks::Warn => {
+                println!("[ WARN ] {message} in {filepath}");
+                Ok(())
+            }
+            BrokenLinks::Error => Err(anyhow!("[ ERROR ] {message} in {filepath}")),
+        }
+    }
+}
+
+/// Attributes added to links to other sites, read from the 'external_links' section of the
+/// config. The icon is any html, placed at the end of the link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalLinkConfig {
+    pub enabled: bool,
+    pub rel: String,
+    pub new_tab: bool,
+    pub class: String,
+    pub icon: String,
+}
+
+impl Default for ExternalLinkConfig {
+    fn default() -> ExternalLinkConfig {
+        ExternalLinkConfig {
+            enabled: false,
+            rel: String::from("noopener noreferrer"),
+            new_tab: true,
+            class: String::from("external"),
+            icon: String::new(),
+        }
+    }
+}
+
+impl ExternalLinkConfig {
+    pub fn from_yaml(yaml: &Yaml) -> ExternalLinkConfig {
+        let default = ExternalLinkConfig::default();
+
+        ExternalLinkConfig {
+            enabled: yaml["enabled"].as_bool().unwrap_or(default.enabled),
+            rel: yaml["rel"].as_str().map_or(default.rel, String::from),
+            new_tab: yaml["new_tab"].as_bool().unwrap_or(default.new_tab),
+            class: yaml["class"].as_str().map_or(default.class, String::from),
+            icon: yaml["icon"].as_str().map_or(default.icon, String::from),
+        }
+    }
+}
+
+/// Replaces '[[Page Title]]', '[[filename]]' and '[[filename|label]]' with links to those
+/// pages, where a '#heading' can be added to the target to link to a heading within it.
+pub fn add_wiki_links<'a>(
+    events: Vec<Event<'a>>,
+    page: &Page,
+    site: &Site,
+) -> Result<Vec<Event<'a>>> {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut text: String = String::new();
+    let mut in_code_block: bool = false;
+
+    // Text is often split over several events around brackets, so it is joined back together.
+    // Inline code is its own event, so only code blocks need to be passed over.
+    for event in events {
+        match event {
+            Event::Text(t) if !in_code_block => text.push_str(&t),
+            event => {
+                match event {
+                    Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                    Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                    _ => (),
+                }
+                output.extend(replace_wiki_links(&std::mem::take(&mut text), page, site)?);
+                output.push(event);
+            }
+        }
+    }
+    output.extend(replace_wiki_links(&text, page, site)?);
+
+    Ok(output)
+}
+
+fn replace_wiki_links<'a>(text: &str, page: &Page, site: &Site) -> Result<Vec<Event<'a>>> {
+    let mut output: Vec<Event> = Vec::new();
+    let mut rest: &str = text;
+
+    while let Some(start) = rest.find("[[") {
+        let end = match rest[start..].find("]]") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let link = &rest[start + 2..end];
+        let (target, label) = link.split_once('|').unwrap_or((link, link));
+        let (target, fragment) = match target.split_once('#') {
+            Some((target, fragment)) => (target.trim(), Some(slugify(fragment))),
+            None => (target.trim(), None),
+        };
+        let label = escape_html(label.trim());
+
+        if !rest[..start].is_empty() {
+            output.push(Event::Text(CowStr::from(rest[..start].to_string())));
+        }
+
+        // '[[#heading]]' links to a heading on the same page
+        let url = match target.is_empty() {
+            true => Some(String::new()),
+            false => find_page(target, site).map(|target| target.url.to_string()),
+        };
+        let html = match (url, fragment) {
+            (Some(url), Some(fragment)) => {
+                format!("<a class=\"wiki-link\" href=\"{url}#{fragment}\">{label}</a>")
+            }
+            (Some(url), None) => format!("<a class=\"wiki-link\" href=\"{url}\">{label}</a>"),
+            (None, _) => {
+                let message = format!("No page found for [[{link}]]");
+                site.broken_links.report(&message, &page.filepath)?;
+                format!("<span class=\"wiki-link broken\">{label}</span>")
+            }
+        };
+        output.push(Event::Html(CowStr::from(html)));
+
+        rest = &rest[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        output.push(Event::Text(CowStr::from(rest.to_string())));
+    }
+
+    Ok(output)
+}
+
+/// Replaces each '[[wiki link]]' in some text with the label it is shown with, for text without
+/// any markup like the search index.
+pub fn strip_wiki_links(text: &str) -> String {
+    let mut output: String = String::new();
+    let mut rest: &str = text;
+
+    while let Some(start) = rest.find("[[") {
+        let end = match rest[start..].find("]]") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let link = &rest[start + 2..end];
+        let label = link.split_once('|').map_or(link, |(_, label)| label);
+
+        output.push_str(&rest[..start]);
+        output.push_str(label.trim());
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Changes links to markdown files in 'content/', like '../blog/other-post.md', into links to
+/// where those pages are written on the site. Links starting with '/' are read from 'content/'.
+pub fn rewrite_markdown_links<'a>(
+    events: Vec<Event<'a>>,
+    page: &Page,
+    site: &Site,
+) -> Result<Vec<Event<'a>>> {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Link(link_type, dest, title)) if is_markdown_link(&dest) => {
+                let (path, fragment) = match dest.split_once('#') {
+                    Some((path, fragment)) => (path, format!("#{fragment}")),
+                    None => (dest.as_ref(), String::new()),
+                };
+                let filepath = resolve_path(&page.filepath, &decode_path(path));
+
+                let dest = match site.pages.iter().find(|p| p.filepath == filepath) {
+                    Some(target) => CowStr::from(format!("{}{fragment}", target.url)),
+                    None => {
+                        let message = format!("No page found for the link to {dest}");
+                        site.broken_links.report(&message, &page.filepath)?;
+                        dest
+                    }
+                };
+                output.push(Event::Start(Tag::Link(link_type, dest, title)));
+            }
+            event => output.push(event),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Adds the attributes from the 'external_links' config to links to other sites, unless the link
+/// is followed by '{.internal}', which is removed either way.
+pub fn mark_external_links<'a>(events: Vec<Event<'a>>, site: &Site) -> Vec<Event<'a>> {
+    let config = &site.external_links;
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut in_external_link: bool = false;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Link(_, dest, title))
+                if config.enabled
+                    && is_other_host(dest, site)
+                    && !is_marked_internal(&events, i) =>
+            {
+                output.push(create_external_link_start(dest, title, config));
+                in_external_link = true;
+            }
+            Event::End(Tag::Link(..)) if in_external_link => {
+                let icon = match config.icon.is_empty() {
+                    true => String::new(),
+                    false => format!(
+                        "<span class=\"external-icon\" aria-hidden=\"true\">{}</span>",
+                        config.icon
+                    ),
+                };
+                output.push(Event::Html(CowStr::from(format!("{icon}</a>"))));
+                in_external_link = false;
+            }
+            Event::Text(text)
+                if text.starts_with(INTERNAL_MARKER)
+                    && i > 0
+                    && matches!(events[i - 1], Event::End(Tag::Link(..))) =>
+            {
+                let rest = &text[INTERNAL_MARKER.len()..];
+                if !rest.is_empty() {
+                    output.push(Event::Text(CowStr::from(rest.to_string())));
+                }
+            }
+            event => output.push(event.clone()),
+        }
+    }
+
+    output
+}
+
+/// Checks if the link starting at `start` is followed by '{.internal}'.
+fn is_marked_internal(events: &[Event], start: usize) -> bool {
+    let end = events[start..]
+        .iter()
+        .position(|event| matches!(event, Event::End(Tag::Link(..))));
+
+    match end.and_then(|end| events.get(start + end + 1)) {
+        Some(Event::Text(text)) => text.starts_with(INTERNAL_MARKER),
+        _ => false,
+    }
+}
+
+fn create_external_link_start<'a>(
+    dest: &str,
+    title: &str,
+    config: &ExternalLinkConfig,
+) -> Event<'a> {
+    let mut href: String = String::new();
+    // Writing to a String can't fail
+    escape_href(&mut href, dest).ok();
+
+    let mut html = format!("<a href=\"{href}\"");
+    if !title.is_empty() {
+        html.push_str(&format!(" title=\"{}\"", escape_html(title)));
+    }
+    if !config.class.is_empty() {
+        html.push_str(&format!(" class=\"{}\"", escape_html(&config.class)));
+    }
+    if !config.rel.is_empty() {
+        html.push_str(&format!(" rel=\"{}\"", escape_html(&config.rel)));
+    }
+    if config.new_tab {
+        html.push_str(" target=\"_blank\"");
+    }
+    html.push('>');
+
+    Event::Html(CowStr::from(html))
+}
+
+/// Checks if a url points to a different host than the site's 'base_url'.
+fn is_other_host(url: &str, site: &Site) -> bool {
+    let is_web_url = matches!(get_scheme(url), Some("http") | Some("https"));
+
+    (is_web_url || url.starts_with("//")) && !site.base_url.is_same_host(url)
+}
+
+/// Gets the scheme of a URL like 'mailto:me@example.com', if it has one.
+pub fn get_scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once(':')?;
+    let valid = !scheme.is_empty()
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+
+    match valid {
+        true => Some(scheme),
+        false => None,
+    }
+}
+
+fn is_markdown_link(dest: &str) -> bool {
+    let path = dest.split(['#', '?']).next().unwrap_or("");
+    !dest.contains("://") && !dest.starts_with("mailto:") && path.ends_with(".md")
+}
+
+/// Works out which file a relative link points to from the file it is in, so
+/// '../blog/post.md' in 'content/notes/a.md' is 'content/blog/post.md'.
+fn resolve_path(from: &str, link: &str) -> String {
+    let mut parts: Vec<&str> = match link.starts_with('/') {
+        true => vec!["content"],
+        false => from.split('/').collect(),
+    };
+    if !link.starts_with('/') {
+        parts.pop();
+    }
+
+    for part in link.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// Decodes the '%20' style escapes used in links to files with spaces in their names.
+pub fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i: usize = 0;
+
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' if i + 2 < bytes.len() => std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).unwrap_or_else(|_| path.to_string())
+}
+
+/// Finds the page a wiki link points to, trying the titles of pages first, then their filenames
+/// and finally their paths in 'content/', ignoring case and punctuation.
+fn find_page<'a>(target: &str, site: &'a Site) -> Option<&'a Page> {
+    let slug = slugify(target);
+    let path = target.trim_start_matches('/').trim_end_matches(".md");
+
+    let by_title: Vec<&Page> = site
+        .pages
+        .iter()
+        .filter(|page| slugify(&page.title) == slug)
+        .collect();
+    let by_filename: Vec<&Page> = site
+        .pages
+        .iter()
+        .filter(|page| slugify(&page.filename) == slug)
+        .collect();
+    let by_path: Vec<&Page> = site
+        .pages
+        .iter()
+        .filter(|page| {
+            let filepath = page.filepath.trim_end_matches(".md").to_lowercase();
+            filepath.ends_with(&format!("/{}", path.to_lowercase()))
+        })
+        .collect();
+
+    for found in [by_title, by_filename, by_path] {
+        if found.len() > 1 {
+            println!(
+                "[ WARN ] [[{target}]] could be any of {}, using {}",
+                found
+                    .iter()
+                    .map(|page| page.filepath.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+                found[0].filepath
+            );
+        }
+        if let Some(page) = found.first() {
+            return Some(page);
+        }
+    }
+
+    None
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_url::BaseUrl;
+    use pulldown_cmark::{html, Parser};
+
+    fn test_site() -> Site {
+        let mut site: Site = Site::default();
+        for (filepath, title, url) in [
+            (
+                "content/blog/first-post.md",
+                "My First Post",
+                "/blog/first-post.html",
+            ),
+            ("content/notes/setup.md", "Setting Up", "/notes/setup.html"),
+            ("content/blog/setup.md", "Setup Again", "/blog/setup.html"),
+        ] {
+            let mut page: Page = Page::new();
+            page.filepath = filepath.to_string();
+            page.filename = filepath.rsplit('/').next().unwrap().replace(".md", "");
+            page.title = title.to_string();
+            page.url = url.to_string();
+            site.pages.push(page);
+        }
+        site
+    }
+
+    fn render(input: &str, site: &Site) -> Result<String> {
+        let events = add_wiki_links(Parser::new(input).collect(), &Page::new(), site)?;
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+        Ok(output)
+    }
+
+    #[test]
+    fn add_wiki_links_test() {
+        let site = test_site();
+
+        assert_eq!(
+            render("See [[my first post]] and [[blog/setup|this]].", &site).unwrap(),
+            "<p>See <a class=\"wiki-link\" href=\"/blog/first-post.html\">my first post</a> and <a class=\"wiki-link\" href=\"/blog/setup.html\">this</a>.</p>\n"
+        );
+        assert_eq!(
+            render("[[first-post#Getting Started]]", &site).unwrap(),
+            "<p><a class=\"wiki-link\" href=\"/blog/first-post.html#getting-started\">first-post#Getting Started</a></p>\n"
+        );
+        assert_eq!(
+            render("`[[first-post]]` and [[Missing]]", &site).unwrap(),
+            "<p><code>[[first-post]]</code> and <span class=\"wiki-link broken\">Missing</span></p>\n"
+        );
+        assert_eq!(
+            render("```sh\nif [[ -f x ]]; then\n```\n\n    [[first-post]]\n", &site).unwrap(),
+            "<pre><code class=\"language-sh\">if [[ -f x ]]; then\n</code></pre>\n<pre><code>[[first-post]]\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn rewrite_markdown_links_test() {
+        let site = test_site();
+        let mut page: Page = Page::new();
+        page.filepath = String::from("content/notes/setup.md");
+
+        let input = "[a](../blog/first-post.md#intro), [b](/blog/setup.md), [c](https://a.com/x.md), [d](missing.md)";
+        let events = rewrite_markdown_links(Parser::new(input).collect(), &page, &site).unwrap();
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+
+        assert_eq!(
+            output,
+            "<p><a href=\"/blog/first-post.html#intro\">a</a>, <a href=\"/blog/setup.html\">b</a>, <a href=\"https://a.com/x.md\">c</a>, <a href=\"missing.md\">d</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn resolve_path_test() {
+        let from = "content/notes/setup.md";
+        assert_eq!(resolve_path(from, "other.md"), "content/notes/other.md");
+        assert_eq!(resolve_path(from, "./a/../b.md"), "content/notes/b.md");
+        assert_eq!(
+            resolve_path(from, "../blog/post.md"),
+            "content/blog/post.md"
+        );
+        assert_eq!(resolve_path(from, "/blog/post.md"), "content/blog/post.md");
+        assert_eq!(decode_path("My%20Post%20(draft).md"), "My Post (draft).md");
+    }
+
+    #[test]
+    fn mark_external_links_test() {
+        let site = Site {
+            external_links: ExternalLinkConfig {
+                enabled: true,
+                icon: String::from("↗"),
+                ..ExternalLinkConfig::default()
+            },
+            ..test_site()
+        };
+
+        let input = "[a](https://github.com \"Code\"), [b](https://www.example.com/x.html), [c](/y.html) and [d](https://a.org){.internal}.";
+        let events = mark_external_links(Parser::new(input).collect(), &site);
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+
+        assert_eq!(
+            output,
+            "<p><a href=\"https://github.com\" title=\"Code\" class=\"external\" rel=\"noopener noreferrer\" target=\"_blank\">a<span class=\"external-icon\" aria-hidden=\"true\">↗</span></a>, <a href=\"https://www.example.com/x.html\">b</a>, <a href=\"/y.html\">c</a> and <a href=\"https://a.org\">d</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn is_other_host_test() {
+        let site = Site {
+            base_url: BaseUrl::parse("https://example.github.io/site").unwrap(),
+            ..Site::default()
+        };
+        assert!(is_other_host("https://github.com/", &site));
+        assert!(is_other_host("//cdn.example.org/a.js", &site));
+        assert!(!is_other_host("https://example.github.io/other/", &site));
+        assert!(!is_other_host("/blog/post.html", &site));
+        assert!(!is_other_host("mailto:me@example.com", &site));
+    }
+
+    #[test]
+    fn broken_wiki_links_test() {
+        let site = Site {
+            broken_links: BrokenLinks::Error,
+            ..test_site()
+        };
+
+        assert!(render("[[Setting Up]]", &site).is_ok());
+        assert!(render("[[Missing]]", &site).is_err());
+    }
+}
@@ -1,14 +1,31 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 
+mod base_url;
+mod callouts;
 mod cli;
 mod directory_handling;
+mod feeds;
+mod headings;
+mod highlighting;
+mod images;
+mod link_checker;
+mod links;
 mod markdown_compiling;
+mod markdown_options;
+mod math;
 mod page_creation;
+mod permalinks;
+mod redirects;
+mod search;
 mod site_data;
+mod sitemap;
+mod structured_data;
+mod summaries;
 
 use cli::Commands;
 use directory_handling::{copy_static, init_directories, move_to_project_root, process_content};
+use link_checker::{check_site_links, ExternalLinks};
 use page_creation::create_page;
 
 fn main() -> Result<()> {
@@ -43,5 +60,21 @@ fn main() -> Result<()> {
             create_page(&file).with_context(|| "Error: Could not create new page")?;
             Ok(())
         }
+        Commands::CheckLinks {
+            list_external,
+            check_external,
+        } => {
+            move_to_project_root().with_context(|| {
+                "[ ERROR ] Could not find find cargo.yaml. Try running 'md_puppy init'"
+            })?;
+            let external = if check_external {
+                ExternalLinks::Check
+            } else if list_external {
+                ExternalLinks::List
+            } else {
+                ExternalLinks::Ignore
+            };
+            check_site_links(external)
+        }
     }
 }
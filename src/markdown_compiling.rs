@@ -1,15 +1,26 @@
 use anyhow::{anyhow, Result};
+use deunicode::deunicode;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
 use serde::Deserialize;
 use yaml_rust::YamlLoader;
 
+use crate::callouts::{add_callouts, separate_callout_fences, strip_callout_marker};
 use crate::directory_handling::check_and_create_directory;
-use crate::site_data::{convert_datetime, Site};
+use crate::headings::{add_heading_ids, create_toc};
+use crate::highlighting::{HighlightStyle, HIGHLIGHT_CSS_PATH};
+use crate::images::render_images;
+use crate::links::{add_wiki_links, mark_external_links, rewrite_markdown_links, strip_wiki_links};
+use crate::markdown_options::read_overrides;
+use crate::math::{extract_math, insert_math, remove_math};
+use crate::permalinks::get_output_file;
+use crate::site_data::{convert_datetime, create_breadcrumbs, Site};
+use crate::structured_data::{create_structured_data, get_image_url};
+use crate::summaries::{create_summary, reading_time, Summary};
 
 #[derive(Clone, Deserialize, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Page {
@@ -21,10 +32,25 @@ pub struct Page {
     pub filepath: String,
     pub filename: String,
     pub output_path: String,
+    pub url: String,
+    pub slug: String,
+    pub aliases: Vec<String>,
+    pub html: String,
+    pub updated: String,
+    pub sitemap: bool,
+    pub noindex: bool,
+    pub image: String,
+    pub author: String,
+    pub markdown: String,
+    pub toc: String,
+    pub markdown_options: Vec<(String, bool)>,
+    pub word_count: usize,
+    pub summary: String,
+    pub summary_text: String,
 }
 
 impl Page {
-    fn new() -> Page {
+    pub fn new() -> Page {
         Page {
             title: String::from("default_title"),
             description: String::from("default_description"),
@@ -34,14 +60,37 @@ impl Page {
             filepath: String::from("content/index.md"),
             filename: String::from("index"),
             output_path: String::from("/"),
+            url: String::from("/index.html"),
+            slug: String::new(),
+            aliases: Vec::new(),
+            html: String::new(),
+            updated: String::new(),
+            sitemap: true,
+            noindex: false,
+            image: String::new(),
+            author: String::new(),
+            markdown: String::new(),
+            toc: String::new(),
+            markdown_options: Vec::new(),
+            word_count: 0,
+            summary: String::new(),
+            summary_text: String::new(),
         }
     }
 
-    pub fn from_file(filename: &str) -> Result<Page> {
-        let mut page: Page = Page::new();
+    pub fn from_file(filename: &str, site: &Site) -> Result<Page> {
         let path: &Path = Path::new(filename);
-        let input: Vec<String> = fs::read_to_string(path)
-            .expect("[ ERROR ] Failed to open file!")
+        let source: String = fs::read_to_string(path).expect("[ ERROR ] Failed to open file!");
+
+        Page::from_source(filename, &source, site)
+    }
+
+    /// Creates a page from the markdown and frontmatter of a file, without reading it from disk.
+    /// The page isn't rendered until `content_to_html` is called, so that links can be resolved
+    /// once every page is known.
+    pub fn from_source(filename: &str, source: &str, site: &Site) -> Result<Page> {
+        let mut page: Page = Page::new();
+        let input: Vec<String> = source
             .splitn(3, "---")
             .filter(|&x| !x.is_empty())
             .map(|x| x.to_string())
@@ -50,9 +99,16 @@ impl Page {
         page.filepath = filename.to_string();
         page.filename = get_filename_from_path(filename);
         page.parse_frontmatter(&input[0])?;
-        page.output_path = get_output_dir(&page.category);
+        page.slug = create_slug(&page, site);
+        page.output_path = if site.mirror_content_paths {
+            get_output_dir_from_path(filename)
+        } else {
+            get_output_dir(&page.category)
+        };
+        page.url = site.permalinks.url_for(&page)?;
+        page.markdown = input[1].to_string();
+        page.word_count = markdown_to_text(&page.markdown).split_whitespace().count();
         page.content = input[1].to_string();
-        page.content_to_html("template/boilerplate.html")?;
 
         Ok(page)
     }
@@ -74,22 +130,54 @@ impl Page {
                     .as_str()
                     .unwrap_or("1970-01-01T00:00:00-0000")
                     .to_string();
-                self.category = fm["category"].as_str().unwrap_or("").to_string();
+                self.updated = fm["updated"].as_str().unwrap_or("").to_string();
+                self.sitemap = fm["sitemap"].as_bool().unwrap_or(true);
+                self.noindex = fm["noindex"].as_bool().unwrap_or(false);
+                self.image = fm["image"].as_str().unwrap_or("").to_string();
+                self.author = fm["author"].as_str().unwrap_or("").to_string();
+                self.markdown_options = read_overrides(&fm["markdown"]);
+                if let Some(math) = fm["math"].as_bool() {
+                    self.markdown_options.push((String::from("math"), math));
+                }
+                self.category = fm["category"]
+                    .as_str()
+                    .unwrap_or("")
+                    .trim_matches('/')
+                    .to_string();
+                self.slug = fm["slug"].as_str().unwrap_or("").to_string();
+                self.aliases = match fm["aliases"].as_vec() {
+                    Some(aliases) => aliases
+                        .iter()
+                        .filter_map(|a| a.as_str())
+                        .map(String::from)
+                        .collect(),
+                    None => fm["aliases"]
+                        .as_str()
+                        .map(String::from)
+                        .into_iter()
+                        .collect(),
+                };
 
                 Ok(())
             }
         }
     }
 
-    pub fn content_to_html(&mut self, template_path: &str) -> Result<()> {
-        self.content = markdown_to_html(&self.content);
-
-        let template: String =
-            fs::read_to_string(template_path).expect("[ ERROR ] Failed to open html template!");
+    pub fn content_to_html(&mut self, site: &Site) -> Result<()> {
+        let markdown = markdown_to_html(&self.content, self, site)?;
+        self.toc = markdown.toc;
+        self.summary = replace_placeholder(&markdown.summary.html, self, site)?;
+        self.summary_text = markdown.summary.text;
+        // A toc on its own line is placed outside of a paragraph, since it is a list
+        self.content = markdown.html.replace("<p>{{ toc }}</p>\n", &self.toc);
+        self.html = remove_markers(&replace_placeholder(&self.content, self, site)?);
+
+        let template: String = fs::read_to_string(&site.template_path)
+            .expect("[ ERROR ] Failed to open html template!");
         let mut output: String = String::new();
 
         for line in template.lines() {
-            output.push_str(&replace_placeholder(line, self)?);
+            output.push_str(&replace_placeholder(line, self, site)?);
             output.push('\n');
         }
 
@@ -99,11 +187,11 @@ impl Page {
     }
 
     pub fn write_to_file(&mut self) -> Result<()> {
-        let output_directory: String = format!("site{}", self.output_path);
+        let output_filename: String = get_output_file(&self.url);
 
-        check_and_create_directory(&output_directory)?;
-
-        let output_filename: String = format!("{}/{}.html", output_directory, self.filename);
+        if let Some(parent) = Path::new(&output_filename).parent() {
+            check_and_create_directory(&parent.display().to_string())?;
+        }
 
         let mut outfile =
             File::create(output_filename).expect("[ ERROR ] Could not create output file!");
@@ -115,6 +203,11 @@ impl Page {
         Ok(())
     }
 
+    /// The number of minutes it takes to read the page.
+    pub fn reading_time(&self, site: &Site) -> usize {
+        reading_time(self.word_count, site.words_per_minute)
+    }
+
     pub fn replace_navbar(&mut self, navbar: &str) {
         self.content = self.content.replace("<div id=topnav>", navbar);
     }
@@ -130,6 +223,10 @@ impl Page {
         }
     }
 
+    pub fn replace_feed_links(&mut self, feed_links: &str) {
+        self.content = self.content.replace("<div id=feed_links>", feed_links);
+    }
+
     pub fn replace_site_name(&mut self, site_name: &str) {
         self.content = self.content.replace("<div id=site_name>", site_name);
     }
@@ -139,21 +236,160 @@ impl Page {
     }
 }
 
-pub fn markdown_to_html(input: &str) -> String {
-    // Setup options and commonmark parser
-    let mut parser_options = pulldown_cmark::Options::empty();
-    parser_options.insert(Options::ENABLE_TABLES);
-    parser_options.insert(Options::ENABLE_FOOTNOTES);
-    parser_options.insert(Options::ENABLE_STRIKETHROUGH);
-    parser_options.insert(Options::ENABLE_TASKLISTS);
-    parser_options.insert(Options::ENABLE_SMART_PUNCTUATION);
-    let parser = Parser::new_ext(input, parser_options);
+/// The html of a page's markdown, along with the table of contents for its headings and its
+/// summary
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub toc: String,
+    pub summary: Summary,
+}
+
+pub fn markdown_to_html(input: &str, page: &Page, site: &Site) -> Result<RenderedMarkdown> {
+    let options = site.markdown.with_overrides(&page.markdown_options);
+
+    let (input, formulas) = match options.math {
+        true => extract_math(input),
+        false => (input.to_string(), Vec::new()),
+    };
+    let input = match options.callouts {
+        true => separate_callout_fences(&input),
+        false => input,
+    };
+
+    let events: Vec<Event> = Parser::new_ext(&input, options.parser_options()).collect();
+
+    let events = insert_math(events, &formulas);
+    let events = match options.callouts {
+        true => add_callouts(events, &site.callouts),
+        false => events,
+    };
+    let events = match options.wiki_links {
+        true => add_wiki_links(events, page, site)?,
+        false => events,
+    };
+    let events = match options.markdown_links {
+        true => rewrite_markdown_links(events, page, site)?,
+        false => events,
+    };
+    let events = mark_external_links(events, site);
+    let events = render_images(events, page, site);
+
+    let events = resolve_heading_placeholders(events, page, site);
+    let (events, headings) = add_heading_ids(events, options.heading_anchors);
+    let events = match &site.highlight {
+        Some(highlighter) if options.highlight => highlighter.highlight_code_blocks(events),
+        _ => events,
+    };
+
+    let summary = create_summary(&events);
 
     // Write to String buffer
     let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
+    html::push_html(&mut html_output, events.into_iter());
 
-    html_output
+    Ok(RenderedMarkdown {
+        html: html_output,
+        toc: create_toc(&headings, &site.toc),
+        summary,
+    })
+}
+
+/// Fills in the placeholders for a page's details in headings, like '# {{title}}', so the
+/// heading's id and its entry in the table of contents are made from the page's title instead
+/// of the placeholder.
+fn resolve_heading_placeholders<'a>(
+    events: Vec<Event<'a>>,
+    page: &Page,
+    site: &Site,
+) -> Vec<Event<'a>> {
+    let mut in_heading: bool = false;
+
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Heading(..)) => {
+                in_heading = true;
+                event
+            }
+            Event::End(Tag::Heading(..)) => {
+                in_heading = false;
+                event
+            }
+            Event::Text(text) if in_heading && text.contains("{{") => {
+                let mut text = text.to_string();
+                for key in TEXT_PLACEHOLDERS {
+                    if text.contains(key) {
+                        let value = get_value(key, page, site).unwrap_or_default();
+                        text = text.replace(key, &value);
+                    }
+                }
+                Event::Text(CowStr::from(text))
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Turns markdown into plain text with all of its formatting and any placeholders removed. Math,
+/// callout markers and fences, and the brackets of wiki links are taken out too.
+pub fn markdown_to_text(input: &str) -> String {
+    let input = separate_callout_fences(&remove_math(input));
+    let mut text: String = String::new();
+    // Text is joined back together, since wiki links and callout markers are split over several
+    // events around their brackets
+    let mut run: String = String::new();
+    let mut starts_paragraph: bool = false;
+    let mut starts_blockquote: bool = false;
+    let mut in_blockquote: bool = false;
+
+    for event in Parser::new_ext(&input, Options::all()) {
+        if let Event::Text(t) = &event {
+            run.push_str(t);
+            continue;
+        }
+
+        let mut run_text: &str = &run;
+        if starts_blockquote {
+            run_text = strip_callout_marker(run_text);
+        }
+        if starts_paragraph && run_text.trim_start().starts_with(":::") {
+            run_text = "";
+        }
+        text.push_str(&strip_wiki_links(run_text));
+        run.clear();
+
+        starts_blockquote = in_blockquote && event == Event::Start(Tag::Paragraph);
+        starts_paragraph = event == Event::Start(Tag::Paragraph);
+        in_blockquote = event == Event::Start(Tag::BlockQuote);
+
+        match event {
+            Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::Rule => text.push(' '),
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(..))
+            | Event::End(Tag::Item)
+            | Event::End(Tag::TableCell)
+            | Event::End(Tag::CodeBlock(_)) => text.push(' '),
+            _ => (),
+        }
+    }
+    text.push_str(&strip_wiki_links(&run));
+
+    remove_placeholders(&text)
+}
+
+/// Removes any placeholders from some text, along with repeated whitespace.
+pub fn remove_placeholders(input: &str) -> String {
+    let mut text: String = input.to_string();
+
+    while let (Some(start), Some(end)) = (text.find("{{"), text.find("}}")) {
+        if end < start {
+            break;
+        }
+        text.replace_range(start..end + 2, "");
+    }
+
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
 }
 
 pub fn get_output_dir(category: &str) -> String {
@@ -163,15 +399,61 @@ pub fn get_output_dir(category: &str) -> String {
     }
 }
 
+/// Gets the output directory for a file from where it sits within the 'content/' folder, so
+/// 'content/projects/foo/notes.md' is placed in '/projects/foo'.
+pub fn get_output_dir_from_path(path: &str) -> String {
+    let parent = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let relative = parent.strip_prefix("content").unwrap_or(parent);
+
+    match relative.to_str() {
+        Some("") | None => String::from("/"),
+        Some(dir) => format!("/{}", dir),
+    }
+}
+
+/// Turns text into a URL-safe slug, transliterating unicode into ascii and replacing anything
+/// that isn't a letter or number with a hyphen, so 'My Post (draft 2)' becomes 'my-post-draft-2'.
+pub fn slugify(input: &str) -> String {
+    let mut slug: String = String::new();
+
+    for c in deunicode(input).to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Creates the slug of a page from its 'slug' in the frontmatter, its title or its filename.
+/// Slugs which end up empty, like one made from a title of only punctuation, fall back to the
+/// filename.
+fn create_slug(page: &Page, site: &Site) -> String {
+    let slug = if !page.slug.is_empty() {
+        slugify(&page.slug)
+    } else if site.slug_from_title && page.filename != "index" {
+        slugify(&page.title)
+    } else {
+        String::new()
+    };
+
+    match slug.is_empty() {
+        true => slugify(&page.filename),
+        false => slug,
+    }
+}
+
 pub fn get_filename_from_path(path: &str) -> String {
     path[(path.rfind('/').unwrap() + 1)..path.len() - 3].to_string()
 }
 
-fn replace_placeholder(input_text: &str, page: &Page) -> Result<String> {
+fn replace_placeholder(input_text: &str, page: &Page, site: &Site) -> Result<String> {
     if let Some(key) = get_placeholder(input_text) {
-        if let Some(value) = get_value(key, page) {
+        if let Some(value) = get_value(key, page, site) {
             // Recursive to get multiple placeholders in line
-            replace_placeholder(&input_text.replace(key, &value), page)
+            replace_placeholder(&input_text.replace(key, &value), page, site)
         } else {
             Err(anyhow!("Invalid key, {}, in template", key))
         }
@@ -180,6 +462,30 @@ fn replace_placeholder(input_text: &str, page: &Page) -> Result<String> {
     }
 }
 
+/// Removes the markers some placeholders leave to be filled in when a page is written, like the
+/// navbar and category indexes, so they don't end up in the html used by feeds.
+fn remove_markers(html: &str) -> String {
+    let mut output: String = html.to_string();
+
+    for marker in [
+        "<base href=base_url>",
+        "<div id=site_name>",
+        "<div id=topnav>",
+        "<div id=feed_links>",
+    ] {
+        output = output.replace(marker, "");
+    }
+
+    while let Some(start) = output.find("<div id='index' class='") {
+        match output[start..].find("'>") {
+            Some(end) => output.replace_range(start..start + end + 2, ""),
+            None => break,
+        }
+    }
+
+    output
+}
+
 fn get_placeholder(template: &str) -> Option<&str> {
     let start_byte = template.find("{{").unwrap_or(0);
     let end_byte = template.find("}}").unwrap_or(0);
@@ -191,7 +497,21 @@ fn get_placeholder(template: &str) -> Option<&str> {
     }
 }
 
-fn get_value(key: &str, page: &Page) -> Option<String> {
+/// Placeholders whose values are plain text, which can be filled in before the markdown is
+/// turned into html
+const TEXT_PLACEHOLDERS: [&str; 9] = [
+    "{{title}}",
+    "{{description}}",
+    "{{category}}",
+    "{{date}}",
+    "{{filename}}",
+    "{{slug}}",
+    "{{word_count}}",
+    "{{reading_time}}",
+    "{{summary_text}}",
+];
+
+fn get_value(key: &str, page: &Page, site: &Site) -> Option<String> {
     match key {
         "{{title}}" => Some(page.title.to_string()),
         "{{description}}" => Some(page.description.to_string()),
@@ -200,9 +520,45 @@ fn get_value(key: &str, page: &Page) -> Option<String> {
         "{{content}}" => Some(page.content.to_string()),
         "{{filename}}" => Some(page.filename.to_string()),
         "{{output_path}}" => Some(page.output_path.to_string()),
+        "{{url}}" => Some(page.url.to_string()),
+        "{{permalink}}" => Some(site.absolute_url(&page.url)),
+        "{{slug}}" => Some(page.slug.to_string()),
+        "{{toc}}" | "{{ toc }}" => Some(page.toc.to_string()),
+        "{{word_count}}" => Some(page.word_count.to_string()),
+        "{{reading_time}}" => Some(page.reading_time(site).to_string()),
+        "{{summary}}" => Some(page.summary.to_string()),
+        "{{summary_text}}" => Some(page.summary_text.to_string()),
+        "{{image}}" => Some(get_image_url(page, site)),
+        "{{author}}" => match page.author.is_empty() {
+            true => Some(site.author.to_string()),
+            false => Some(page.author.to_string()),
+        },
+        "{{structured_data}}" => Some(create_structured_data(page, site)),
+        "{{robots}}" => match page.noindex {
+            true => Some(String::from("noindex")),
+            false => Some(String::from("index, follow")),
+        },
         "{{base_url}}" => Some(String::from("<base href=base_url>")),
         "{{site_name}}" => Some(String::from("<div id=site_name>")),
         "{{topnav}}" => Some(String::from("<div id=topnav>")),
+        "{{feed_links}}" => Some(String::from("<div id=feed_links>")),
+        "{{highlight_css}}" => match &site.highlight {
+            Some(highlighter) if highlighter.style == HighlightStyle::Classes => Some(format!(
+                "<link rel=\"stylesheet\" href=\"{HIGHLIGHT_CSS_PATH}\">"
+            )),
+            _ => Some(String::new()),
+        },
+        "{{breadcrumbs}}" => Some(create_breadcrumbs(&page.category)),
+        url_key if url_key.starts_with("{{ absolute_url ") => {
+            let path = url_key
+                .strip_prefix("{{ absolute_url ")?
+                .strip_suffix("}}")?
+                .trim();
+            match path.is_empty() {
+                true => None,
+                false => Some(site.absolute_url(path)),
+            }
+        }
         youtube_key if youtube_key.contains("youtube") => Some(embed_youtube(youtube_key)),
         index if index.contains("index") => {
             let category = index
@@ -259,6 +615,9 @@ title: example_title
 description: example_description
 category: example_category
 date: example_date
+slug: example-slug
+aliases:
+  - /old/example.html
 ";
         page.parse_frontmatter(frontmatter)
             .expect("[ ERROR ] Failed to parse frontatter!");
@@ -267,6 +626,8 @@ date: example_date
         assert_eq!(page.description, String::from("example_description"));
         assert_eq!(page.category, String::from("example_category"));
         assert_eq!(page.date, String::from("example_date"));
+        assert_eq!(page.slug, String::from("example-slug"));
+        assert_eq!(page.aliases, vec![String::from("/old/example.html")]);
     }
 
     // #[test]
@@ -294,6 +655,94 @@ date: example_date
         assert_eq!(output, String::from("/test"));
     }
 
+    #[test]
+    fn get_output_dir_from_path_test() {
+        let output: String = get_output_dir_from_path("content/index.md");
+        assert_eq!(output, String::from("/"));
+        let output: String = get_output_dir_from_path("content/blog/notes.md");
+        assert_eq!(output, String::from("/blog"));
+        let output: String = get_output_dir_from_path("content/projects/foo/notes.md");
+        assert_eq!(output, String::from("/projects/foo"));
+    }
+
+    #[test]
+    fn heading_placeholder_test() {
+        let site = Site::default();
+        let mut page = Page::new();
+        page.title = String::from("My Post");
+        page.slug = String::from("my-post");
+
+        let output = markdown_to_html("# {{title}}\n\n## {{slug}} notes\n", &page, &site).unwrap();
+        assert_eq!(
+            output.html,
+            "<h1 id=\"my-post\">My Post</h1>\n<h2 id=\"my-post-notes\">my-post notes</h2>\n"
+        );
+        assert!(output
+            .toc
+            .contains("<a href=\"#my-post-notes\">my-post notes</a>"));
+    }
+
+    #[test]
+    fn markdown_to_text_test() {
+        let input = "# {{title}}\n\nSome *styled* text,\nwith `code`.\n\n- one\n- two\n";
+        assert_eq!(
+            markdown_to_text(input),
+            "Some styled text, with code. one two"
+        );
+
+        let input = "See [[Story]] and [[a/b|this]], $x^2$ is\n$$\ny\n$$\n\n> [!NOTE] Heads up\n> Callout.\n\n:::tip\nFenced.\n:::\n";
+        assert_eq!(
+            markdown_to_text(input),
+            "See Story and this, is Heads up Callout. Fenced."
+        );
+    }
+
+    #[test]
+    fn absolute_url_placeholder_test() {
+        let site = Site::default();
+        let page = Page::new();
+        let output = "<a href=\"https://www.example.com/a.html\">";
+
+        let input = "<a href=\"{{ absolute_url /a.html }}\">";
+        assert_eq!(replace_placeholder(input, &page, &site).unwrap(), output);
+        let input = "<a href=\"{{ absolute_url /a.html}}\">";
+        assert_eq!(replace_placeholder(input, &page, &site).unwrap(), output);
+        assert!(replace_placeholder("{{ absolute_url }}", &page, &site).is_err());
+    }
+
+    #[test]
+    fn remove_markers_test() {
+        let html = "<div id=topnav><p>Posts:</p>\n<div id='index' class='blog/fiction'>\n";
+        assert_eq!(remove_markers(html), "<p>Posts:</p>\n\n");
+    }
+
+    #[test]
+    fn slugify_test() {
+        assert_eq!(slugify("My Post (draft 2)"), "my-post-draft-2");
+        assert_eq!(slugify("Crème brûlée & Café"), "creme-brulee-cafe");
+        assert_eq!(slugify("  --already-a-slug--  "), "already-a-slug");
+        assert_eq!(slugify("index"), "index");
+    }
+
+    #[test]
+    fn create_slug_test() {
+        let site = Site {
+            slug_from_title: true,
+            ..Site::default()
+        };
+        let slug = |frontmatter: &str| {
+            let source = format!("---\n{frontmatter}\n---\nBody\n");
+            Page::from_source("content/blog/My Post.md", &source, &site)
+                .unwrap()
+                .slug
+        };
+
+        assert_eq!(slug("title: A Title"), "a-title");
+        assert_eq!(slug("slug: ../Other Place/"), "other-place");
+        assert_eq!(slug("title: \"?!\""), "my-post");
+        assert_eq!(slug("slug: \"//\""), "my-post");
+    }
+
     #[test]
     fn get_filename_from_path_test() {
         let output: String = get_filename_from_path("site/example/index.md");
@@ -0,0 +1,159 @@
+use pulldown_cmark::Options;
+use yaml_rust::Yaml;
+
+/// Which markdown extensions are used when rendering pages, read from the 'markdown' section of
+/// the config. Pages can turn any of them on or off with a 'markdown' section in their
+/// frontmatter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkdownOptions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    pub smart_punctuation: bool,
+    pub heading_attributes: bool,
+    pub math: bool,
+    pub callouts: bool,
+    pub wiki_links: bool,
+    pub markdown_links: bool,
+    pub highlight: bool,
+    pub heading_anchors: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> MarkdownOptions {
+        MarkdownOptions {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: true,
+            heading_attributes: true,
+            math: false,
+            callouts: true,
+            wiki_links: true,
+            markdown_links: true,
+            highlight: true,
+            heading_anchors: false,
+        }
+    }
+}
+
+impl MarkdownOptions {
+    /// Reads the options from a 'markdown' section, keeping the value from `defaults` for any
+    /// which aren't set.
+    pub fn from_yaml(yaml: &Yaml, defaults: &MarkdownOptions) -> MarkdownOptions {
+        let mut options = defaults.clone();
+        options.apply(&read_overrides(yaml));
+        options
+    }
+
+    /// Returns a copy of the options with a page's own settings applied.
+    pub fn with_overrides(&self, overrides: &[(String, bool)]) -> MarkdownOptions {
+        let mut options = self.clone();
+        options.apply(overrides);
+        options
+    }
+
+    fn apply(&mut self, overrides: &[(String, bool)]) {
+        for (name, value) in overrides {
+            match self.get_mut(name) {
+                Some(option) => *option = *value,
+                None => println!("[ WARN ] Unknown markdown option {name}"),
+            }
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "tables" => Some(&mut self.tables),
+            "footnotes" => Some(&mut self.footnotes),
+            "strikethrough" => Some(&mut self.strikethrough),
+            "tasklists" => Some(&mut self.tasklists),
+            "smart_punctuation" => Some(&mut self.smart_punctuation),
+            "heading_attributes" => Some(&mut self.heading_attributes),
+            "math" => Some(&mut self.math),
+            "callouts" => Some(&mut self.callouts),
+            "wiki_links" => Some(&mut self.wiki_links),
+            "markdown_links" => Some(&mut self.markdown_links),
+            "highlight" => Some(&mut self.highlight),
+            "heading_anchors" => Some(&mut self.heading_anchors),
+            _ => None,
+        }
+    }
+
+    /// The options pulldown-cmark parses with.
+    pub fn parser_options(&self) -> Options {
+        let mut options = Options::empty();
+
+        for (enabled, option) in [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.tasklists, Options::ENABLE_TASKLISTS),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+        ] {
+            if enabled {
+                options.insert(option);
+            }
+        }
+
+        options
+    }
+}
+
+/// Reads the options set in a 'markdown' section of the config or a page's frontmatter.
+pub fn read_overrides(yaml: &Yaml) -> Vec<(String, bool)> {
+    match yaml.as_hash() {
+        Some(hash) => hash
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = name.as_str()?;
+                match value.as_bool() {
+                    Some(value) => Some((name.to_string(), value)),
+                    None => {
+                        println!("[ WARN ] Markdown option {name} should be true or false");
+                        None
+                    }
+                }
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn from_yaml_test() {
+        let yaml = &YamlLoader::load_from_str("smart_punctuation: false\nmath: true").unwrap()[0];
+        let options = MarkdownOptions::from_yaml(yaml, &MarkdownOptions::default());
+
+        assert!(!options.smart_punctuation);
+        assert!(options.math);
+        assert!(options.tables);
+        assert!(!options
+            .parser_options()
+            .contains(Options::ENABLE_SMART_PUNCTUATION));
+        assert!(options.parser_options().contains(Options::ENABLE_FOOTNOTES));
+    }
+
+    #[test]
+    fn with_overrides_test() {
+        let options = MarkdownOptions::default();
+        let overrides = vec![
+            (String::from("tables"), false),
+            (String::from("heading_anchors"), true),
+        ];
+        let page_options = options.with_overrides(&overrides);
+
+        assert!(!page_options.tables);
+        assert!(page_options.heading_anchors);
+        assert!(page_options.footnotes);
+        assert!(options.tables);
+    }
+}
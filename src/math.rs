@@ -0,0 +1,1025 @@
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+
+// Formulas are swapped for these while the markdown is parsed, so characters like '_' and '*'
+// in the TeX aren't read as markdown
+const TOKEN_START: char = '\u{E000}';
+const TOKEN_END: char = '\u{E001}';
+
+/// Commands whose argument is read as text instead of as math
+const TEXT_COMMANDS: [&str; 6] = ["text", "textrm", "textit", "textbf", "mbox", "operatorname"];
+
+/// Operators written as words, like '\sin'
+const FUNCTIONS: [&str; 22] = [
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "det", "dim", "ker", "deg", "gcd", "arg",
+];
+
+/// Operators written as words which put their subscript underneath them, like '\lim'
+const LIMIT_FUNCTIONS: [&str; 6] = ["lim", "max", "min", "sup", "inf", "Pr"];
+
+/// A formula taken out of the markdown by `extract_math`, along with its MathML
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formula {
+    pub html: String,
+    pub display: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SymbolKind {
+    Identifier,
+    Operator,
+    /// An operator like '\sum' which puts its scripts above and below it
+    LargeOperator,
+    /// An operator like '\int' which is drawn large but keeps its scripts to the side
+    Integral,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Command(String),
+    Text(String),
+    Number(String),
+    Char(char),
+    Open,
+    Close,
+    Superscript,
+    Subscript,
+    NextCell,
+    NextRow,
+}
+
+/// Finds the '$...$' and '$$...$$' formulas in markdown outside of code, and replaces each of
+/// them with a token. Returns the markdown along with the MathML for each token, which is put
+/// back by `insert_math` once the markdown has been parsed.
+pub fn extract_math(input: &str) -> (String, Vec<Formula>) {
+    let mut output: String = String::new();
+    let mut formulas: Vec<Formula> = Vec::new();
+    let mut last: usize = 0;
+
+    for (range, tex, display) in find_formulas(input) {
+        output.push_str(&input[last..range.start]);
+        output.push_str(&format!("{TOKEN_START}{}{TOKEN_END}", formulas.len()));
+        formulas.push(Formula {
+            html: render_math(tex, display),
+            display,
+        });
+        last = range.end;
+    }
+
+    output.push_str(&input[last..]);
+    (output, formulas)
+}
+
+/// Takes the formulas out of markdown, for text without any markup like the search index.
+pub fn remove_math(input: &str) -> String {
+    let mut output: String = String::new();
+    let mut last: usize = 0;
+
+    for (range, _, _) in find_formulas(input) {
+        output.push_str(&input[last..range.start]);
+        last = range.end;
+    }
+
+    output.push_str(&input[last..]);
+    output
+}
+
+/// Gets where each formula in markdown is, along with its TeX and whether it is display math.
+fn find_formulas(input: &str) -> Vec<(Range<usize>, &str, bool)> {
+    let skipped: Vec<Range<usize>> = Parser::new_ext(input, Options::all())
+        .into_offset_iter()
+        .filter(|(event, _)| {
+            matches!(
+                event,
+                Event::Code(_) | Event::Html(_) | Event::Start(Tag::CodeBlock(_))
+            )
+        })
+        .map(|(_, range)| range)
+        .collect();
+
+    let bytes = input.as_bytes();
+    let mut formulas: Vec<(Range<usize>, &str, bool)> = Vec::new();
+    let mut i: usize = 0;
+
+    while i < bytes.len() {
+        if let Some(range) = skipped.iter().find(|range| range.contains(&i)) {
+            i = range.end;
+            continue;
+        }
+
+        match bytes[i] {
+            // Skip escaped characters, so '\$' stays a dollar sign
+            b'\\' => i += 2,
+            b'$' => match find_formula(input, i, &skipped) {
+                Some((end, tex, display)) => {
+                    formulas.push((i..end, tex, display));
+                    i = end;
+                }
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+
+    formulas
+}
+
+/// Swaps the tokens left by `extract_math` back for the MathML of each formula. Display math is
+/// a block, so the paragraph around it is closed before it and opened again after it, and a
+/// paragraph holding only display math is dropped.
+pub fn insert_math<'a>(events: Vec<Event<'a>>, formulas: &[Formula]) -> Vec<Event<'a>> {
+    if formulas.is_empty() {
+        return events;
+    }
+
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut in_paragraph: bool = false;
+    // Set when display math has closed the paragraph it was in, until it is opened again
+    let mut closed: bool = false;
+
+    for event in events {
+        let (text, is_html) = match &event {
+            Event::Text(text) if text.contains(TOKEN_START) => (text.to_string(), false),
+            Event::Html(html) if html.contains(TOKEN_START) => (html.to_string(), true),
+            Event::SoftBreak if closed => continue,
+            Event::End(Tag::Paragraph) if closed => {
+                closed = false;
+                in_paragraph = false;
+                continue;
+            }
+            _ => {
+                match event {
+                    Event::Start(Tag::Paragraph) => in_paragraph = true,
+                    Event::End(Tag::Paragraph) => in_paragraph = false,
+                    _ => reopen_paragraph(&mut output, &mut closed),
+                }
+                output.push(event);
+                continue;
+            }
+        };
+
+        let push_text = |output: &mut Vec<Event>, closed: &mut bool, text: &str| {
+            let text = if *closed { text.trim_start() } else { text };
+            if text.is_empty() {
+                return;
+            }
+            reopen_paragraph(output, closed);
+            match is_html {
+                true => output.push(Event::Html(CowStr::from(text.to_string()))),
+                false => output.push(Event::Text(CowStr::from(text.to_string()))),
+            }
+        };
+
+        let mut rest: &str = &text;
+        while let Some(start) = rest.find(TOKEN_START) {
+            let after = &rest[start + TOKEN_START.len_utf8()..];
+            let formula = after.find(TOKEN_END).and_then(|end| {
+                let index: usize = after[..end].parse().ok()?;
+                Some((formulas.get(index)?, end))
+            });
+
+            match formula {
+                Some((formula, end)) => {
+                    push_text(&mut output, &mut closed, &rest[..start]);
+                    if formula.display && in_paragraph && !is_html {
+                        close_paragraph(&mut output, &mut closed);
+                    } else {
+                        reopen_paragraph(&mut output, &mut closed);
+                    }
+                    output.push(Event::Html(CowStr::from(match formula.display {
+                        true => format!("{}\n", formula.html),
+                        false => formula.html.to_string(),
+                    })));
+                    rest = &after[end + TOKEN_END.len_utf8()..];
+                }
+                None => break,
+            }
+        }
+        push_text(&mut output, &mut closed, rest);
+    }
+
+    output
+}
+
+/// Closes the paragraph display math is in, dropping it if nothing has been written to it yet.
+fn close_paragraph(output: &mut Vec<Event>, closed: &mut bool) {
+    if *closed {
+        return;
+    }
+    while output.last() == Some(&Event::SoftBreak) {
+        output.pop();
+    }
+    match output.last() {
+        Some(Event::Start(Tag::Paragraph)) => {
+            output.pop();
+        }
+        _ => output.push(Event::End(Tag::Paragraph)),
+    }
+    *closed = true;
+}
+
+/// Opens the paragraph again after display math, once there is more of it to write.
+fn reopen_paragraph(output: &mut Vec<Event>, closed: &mut bool) {
+    if *closed {
+        output.push(Event::Start(Tag::Paragraph));
+        *closed = false;
+    }
+}
+
+/// Finds the end of the formula starting at `start`, along with its TeX and whether it is
+/// display math. Inline math can't start or end with a space, or be followed by a digit, so
+/// prices like '$5 and $10' are left alone. Formulas can't contain any of the `skipped` code.
+fn find_formula<'a>(
+    input: &'a str,
+    start: usize,
+    skipped: &[Range<usize>],
+) -> Option<(usize, &'a str, bool)> {
+    let rest = &input[start..];
+
+    if let Some(display) = rest.strip_prefix("$$") {
+        let end = start + 4 + display.find("$$")?;
+        let tex = display[..end - start - 4].trim();
+        let contains_code = skipped.iter().any(|r| r.start > start && r.start < end);
+        return match tex.is_empty() || contains_code {
+            true => None,
+            false => Some((end, tex, true)),
+        };
+    }
+
+    let inline = &rest[1..];
+    if inline.starts_with(char::is_whitespace) || inline.starts_with('$') {
+        return None;
+    }
+
+    let bytes = inline.as_bytes();
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        if skipped.iter().any(|r| r.contains(&(start + 1 + i))) {
+            return None;
+        }
+
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\n'
+                if inline[i + 1..]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with('\n') =>
+            {
+                return None
+            }
+            b'$' => {
+                let tex = &inline[..i];
+                let closes = !tex.ends_with(char::is_whitespace)
+                    && !inline[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+                if closes {
+                    return Some((start + i + 2, tex, false));
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Converts a formula to MathML, falling back to the TeX in a code element if it can't be.
+fn render_math(tex: &str, display: bool) -> String {
+    match tex_to_mathml(tex, display) {
+        Ok(mathml) => mathml,
+        Err(e) => {
+            println!("[ WARN ] Could not convert math '{tex}', leaving it as TeX: {e}");
+            let delimiter = if display { "$$" } else { "$" };
+            format!(
+                "<code class=\"math\">{delimiter}{}{delimiter}</code>",
+                escape_html(tex)
+            )
+        }
+    }
+}
+
+/// Converts a formula written in a subset of TeX to MathML, keeping the TeX as an annotation.
+pub fn tex_to_mathml(tex: &str, display: bool) -> Result<String> {
+    let mut parser = MathParser {
+        tokens: tokenize(tex)?,
+        pos: 0,
+    };
+    let row = parser.parse_row()?;
+    if let Some(token) = parser.next() {
+        return Err(anyhow!("unexpected {}", describe(&token)));
+    }
+
+    let display = if display { " display=\"block\"" } else { "" };
+    Ok(format!(
+        "<math{display}><semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        row.concat(),
+        escape_html(tex)
+    ))
+}
+
+fn tokenize(tex: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = tex.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i: usize = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                i += 1;
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                if i == start {
+                    let c = chars.get(i).ok_or_else(|| anyhow!("'\\' at the end"))?;
+                    i += 1;
+                    match c {
+                        '\\' => tokens.push(Token::NextRow),
+                        c => tokens.push(Token::Command(c.to_string())),
+                    }
+                    continue;
+                }
+
+                let name: String = chars[start..i].iter().collect();
+                if TEXT_COMMANDS.contains(&name.as_str()) {
+                    while chars.get(i).map_or(false, |c| c.is_whitespace()) {
+                        i += 1;
+                    }
+                    if chars.get(i) != Some(&'{') {
+                        return Err(anyhow!("\\{name} needs its text in braces"));
+                    }
+                    let text_start = i + 1;
+                    let mut depth: usize = 0;
+                    loop {
+                        match chars.get(i) {
+                            Some('{') => depth += 1,
+                            Some('}') if depth == 1 => break,
+                            Some('}') => depth -= 1,
+                            Some(_) => (),
+                            None => return Err(anyhow!("missing '}}' after \\{name}")),
+                        }
+                        i += 1;
+                    }
+                    let text: String = chars[text_start..i].iter().collect();
+                    i += 1;
+                    tokens.push(Token::Command(name));
+                    tokens.push(Token::Text(text));
+                } else {
+                    tokens.push(Token::Command(name));
+                }
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit()
+                        || (chars[i] == '.'
+                            && chars.get(i + 1).map_or(false, |c| c.is_ascii_digit())))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Number(chars[start..i].iter().collect()));
+            }
+            c => {
+                i += 1;
+                match c {
+                    '{' => tokens.push(Token::Open),
+                    '}' => tokens.push(Token::Close),
+                    '^' => tokens.push(Token::Superscript),
+                    '_' => tokens.push(Token::Subscript),
+                    '&' => tokens.push(Token::NextCell),
+                    c if c.is_whitespace() => (),
+                    c => tokens.push(Token::Char(c)),
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct MathParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl MathParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(anyhow!(
+                "expected {} but found {}",
+                describe(&expected),
+                describe(&token)
+            )),
+            None => Err(anyhow!("expected {} at the end", describe(&expected))),
+        }
+    }
+
+    /// Parses everything up to the end of the current group, cell or '\right'.
+    fn parse_row(&mut self) -> Result<Vec<String>> {
+        let mut row: Vec<String> = Vec::new();
+
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Close | Token::NextCell | Token::NextRow => break,
+                Token::Command(name) if name == "right" || name == "end" => break,
+                _ => row.push(self.parse_scripts()?),
+            }
+        }
+
+        Ok(row)
+    }
+
+    fn parse_scripts(&mut self) -> Result<String> {
+        let (base, limits) = self.parse_atom()?;
+        let mut subscript: Option<String> = None;
+        let mut superscript: Option<String> = None;
+
+        loop {
+            match self.peek() {
+                Some(Token::Subscript) if subscript.is_none() => {
+                    self.next();
+                    subscript = Some(self.parse_argument()?);
+                }
+                Some(Token::Superscript) if superscript.is_none() => {
+                    self.next();
+                    superscript = Some(self.parse_argument()?);
+                }
+                _ => break,
+            }
+        }
+
+        let (under, over, both) = match limits {
+            true => ("munder", "mover", "munderover"),
+            false => ("msub", "msup", "msubsup"),
+        };
+        Ok(match (subscript, superscript) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{under}>{base}{sub}</{under}>"),
+            (None, Some(sup)) => format!("<{over}>{base}{sup}</{over}>"),
+            (Some(sub), Some(sup)) => format!("<{both}>{base}{sub}{sup}</{both}>"),
+        })
+    }
+
+    /// Parses a single argument, like the '2' in 'x^2' or the '{a+b}' in '\frac{a+b}{2}'.
+    fn parse_argument(&mut self) -> Result<String> {
+        // Only the first digit of a number is used, so 'x^23' is 'x²3' like in TeX
+        if let Some(Token::Number(number)) = self.peek() {
+            if number.chars().count() > 1 {
+                let first: String = number.chars().take(1).collect();
+                let rest: String = number.chars().skip(1).collect();
+                self.tokens[self.pos] = Token::Number(rest);
+                return Ok(format!("<mn>{first}</mn>"));
+            }
+        }
+
+        match self.peek() {
+            None | Some(Token::Close) => Err(anyhow!("missing an argument")),
+            _ => Ok(self.parse_atom()?.0),
+        }
+    }
+
+    /// Parses one atom, returning its MathML and whether scripts should go above and below it.
+    fn parse_atom(&mut self) -> Result<(String, bool)> {
+        let token = self
+            .next()
+            .ok_or_else(|| anyhow!("expected more at the end"))?;
+
+        let atom = match token {
+            Token::Number(number) => format!("<mn>{number}</mn>"),
+            Token::Char(c) => char_to_mathml(c),
+            Token::Open => {
+                let mut row = self.parse_row()?;
+                self.expect(Token::Close)?;
+                match row.len() {
+                    1 => row.remove(0),
+                    _ => format!("<mrow>{}</mrow>", row.concat()),
+                }
+            }
+            // Scripts with nothing before them, like '{}^{14}C' written as '^{14}C'
+            Token::Superscript | Token::Subscript => {
+                self.pos -= 1;
+                String::from("<mrow></mrow>")
+            }
+            Token::Command(name) => return self.parse_command(&name),
+            token => return Err(anyhow!("unexpected {}", describe(&token))),
+        };
+
+        Ok((atom, false))
+    }
+
+    fn parse_command(&mut self, name: &str) -> Result<(String, bool)> {
+        if let Some((symbol, kind)) = get_symbol(name) {
+            return Ok(match kind {
+                SymbolKind::Identifier if symbol.chars().all(char::is_uppercase) => {
+                    (format!("<mi mathvariant=\"normal\">{symbol}</mi>"), false)
+                }
+                SymbolKind::Identifier => (format!("<mi>{symbol}</mi>"), false),
+                SymbolKind::Operator => (format!("<mo>{symbol}</mo>"), false),
+                SymbolKind::LargeOperator => (
+                    format!("<mo largeop=\"true\" movablelimits=\"true\">{symbol}</mo>"),
+                    true,
+                ),
+                SymbolKind::Integral => (format!("<mo largeop=\"true\">{symbol}</mo>"), false),
+            });
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok((format!("<mi>{name}</mi>"), false));
+        }
+        if LIMIT_FUNCTIONS.contains(&name) {
+            return Ok((
+                format!("<mo movablelimits=\"true\" form=\"prefix\">{name}</mo>"),
+                true,
+            ));
+        }
+
+        let atom = match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                format!("<mfrac>{numerator}{denominator}</mfrac>")
+            }
+            "binom" => {
+                let top = self.parse_argument()?;
+                let bottom = self.parse_argument()?;
+                format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{top}{bottom}</mfrac><mo>)</mo></mrow>")
+            }
+            "sqrt" => match self.peek() {
+                Some(Token::Char('[')) => {
+                    self.next();
+                    let index = self.parse_optional_argument()?;
+                    let radicand = self.parse_argument()?;
+                    format!("<mroot>{radicand}{index}</mroot>")
+                }
+                _ => format!("<msqrt>{}</msqrt>", self.parse_argument()?),
+            },
+            "hat" | "widehat" | "bar" | "overline" | "vec" | "dot" | "ddot" | "tilde"
+            | "widetilde" => {
+                let accent = match name {
+                    "hat" | "widehat" => "^",
+                    "bar" | "overline" => "¯",
+                    "vec" => "→",
+                    "dot" => "˙",
+                    "ddot" => "¨",
+                    _ => "~",
+                };
+                let base = self.parse_argument()?;
+                format!("<mover accent=\"true\">{base}<mo>{accent}</mo></mover>")
+            }
+            "underline" => {
+                let base = self.parse_argument()?;
+                format!("<munder accentunder=\"true\">{base}<mo>_</mo></munder>")
+            }
+            "mathbf" | "boldsymbol" | "mathit" | "mathrm" | "mathbb" | "mathcal" | "mathfrak"
+            | "mathsf" | "mathtt" => {
+                let variant = match name {
+                    "mathbf" | "boldsymbol" => "bold",
+                    "mathit" => "italic",
+                    "mathrm" => "normal",
+                    "mathbb" => "double-struck",
+                    "mathcal" => "script",
+                    "mathfrak" => "fraktur",
+                    "mathsf" => "sans-serif",
+                    _ => "monospace",
+                };
+                self.parse_argument()?
+                    .replace("<mi>", &format!("<mi mathvariant=\"{variant}\">"))
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mbox" | "operatorname" => {
+                let text = match self.next() {
+                    Some(Token::Text(text)) => escape_html(&text),
+                    _ => return Err(anyhow!("\\{name} needs its text in braces")),
+                };
+                match name {
+                    "operatorname" => format!("<mi>{text}</mi>"),
+                    _ => format!("<mtext>{text}</mtext>"),
+                }
+            }
+            "left" => {
+                let open = self.parse_delimiter()?;
+                let row = self.parse_row()?;
+                match self.next() {
+                    Some(Token::Command(name)) if name == "right" => (),
+                    _ => return Err(anyhow!("\\left is missing its \\right")),
+                }
+                let close = self.parse_delimiter()?;
+                format!(
+                    "<mrow>{}{}{}</mrow>",
+                    fence(&open),
+                    row.concat(),
+                    fence(&close)
+                )
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" => format!("<mo>{}</mo>", self.parse_delimiter()?),
+            "begin" => self.parse_environment()?,
+            "," => String::from("<mspace width=\"0.167em\"></mspace>"),
+            ":" | ">" => String::from("<mspace width=\"0.222em\"></mspace>"),
+            ";" => String::from("<mspace width=\"0.278em\"></mspace>"),
+            " " => String::from("<mspace width=\"0.25em\"></mspace>"),
+            "!" => String::from("<mspace width=\"-0.167em\"></mspace>"),
+            "quad" => String::from("<mspace width=\"1em\"></mspace>"),
+            "qquad" => String::from("<mspace width=\"2em\"></mspace>"),
+            "{" | "}" | "|" | "$" | "%" | "#" | "&" | "_" => {
+                format!("<mo>{}</mo>", escape_html(name))
+            }
+            "displaystyle" | "textstyle" => String::from("<mrow></mrow>"),
+            name => return Err(anyhow!("unknown command \\{name}")),
+        };
+
+        Ok((atom, false))
+    }
+
+    /// Parses the contents of the brackets in '\sqrt[3]{x}', after the opening bracket.
+    fn parse_optional_argument(&mut self) -> Result<String> {
+        let start = self.pos;
+        let mut depth: usize = 0;
+
+        loop {
+            match self.next() {
+                Some(Token::Open) => depth += 1,
+                Some(Token::Close) => depth = depth.saturating_sub(1),
+                Some(Token::Char(']')) if depth == 0 => break,
+                Some(_) => (),
+                None => return Err(anyhow!("missing ']'")),
+            }
+        }
+
+        let mut parser = MathParser {
+            tokens: self.tokens[start..self.pos - 1].to_vec(),
+            pos: 0,
+        };
+        let row = parser.parse_row()?;
+        match parser.next() {
+            Some(token) => Err(anyhow!("unexpected {}", describe(&token))),
+            None => Ok(format!("<mrow>{}</mrow>", row.concat())),
+        }
+    }
+
+    /// Parses the delimiter after '\left', '\right' or '\big', where '.' means no delimiter.
+    fn parse_delimiter(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Char('.')) => Ok(String::new()),
+            Some(Token::Char(c)) => Ok(escape_html(&c.to_string())),
+            Some(Token::Command(name)) => match name.as_str() {
+                "{" | "lbrace" => Ok(String::from("{")),
+                "}" | "rbrace" => Ok(String::from("}")),
+                "|" | "Vert" => Ok(String::from("‖")),
+                name => match get_symbol(name) {
+                    Some((symbol, SymbolKind::Operator)) => Ok(symbol.to_string()),
+                    _ => Err(anyhow!("\\{name} can't be used as a delimiter")),
+                },
+            },
+            _ => Err(anyhow!("missing a delimiter")),
+        }
+    }
+
+    /// Parses a '\begin{...}' environment into a table, after the '\begin'.
+    fn parse_environment(&mut self) -> Result<String> {
+        let name = self.parse_environment_name()?;
+        let (open, close, columnalign) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", ""),
+            "pmatrix" => ("(", ")", ""),
+            "bmatrix" => ("[", "]", ""),
+            "Bmatrix" => ("{", "}", ""),
+            "vmatrix" => ("|", "|", ""),
+            "Vmatrix" => ("‖", "‖", ""),
+            "cases" => ("{", "", " columnalign=\"left left\""),
+            "aligned" | "align" | "align*" | "split" => ("", "", " columnalign=\"right left\""),
+            "gathered" | "gather" | "gather*" => ("", "", ""),
+            name => return Err(anyhow!("unknown environment {name}")),
+        };
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut row: Vec<String> = Vec::new();
+        loop {
+            row.push(self.parse_row()?.concat());
+            match self.next() {
+                Some(Token::NextCell) => (),
+                Some(Token::NextRow) => rows.push(std::mem::take(&mut row)),
+                Some(Token::Command(end)) if end == "end" => {
+                    if self.parse_environment_name()? != name {
+                        return Err(anyhow!("\\begin{{{name}}} ends with a different \\end"));
+                    }
+                    break;
+                }
+                _ => return Err(anyhow!("\\begin{{{name}}} is missing its \\end")),
+            }
+        }
+        // A '\\' at the end of the last row doesn't start a new one
+        if row.len() > 1 || !row[0].is_empty() {
+            rows.push(row);
+        }
+
+        let mut table: String = format!("<mtable{columnalign}>");
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str(&format!("<mtd>{cell}</mtd>"));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        Ok(format!(
+            "<mrow>{}{table}{}</mrow>",
+            fence(open),
+            fence(close)
+        ))
+    }
+
+    fn parse_environment_name(&mut self) -> Result<String> {
+        self.expect(Token::Open)?;
+        let mut name: String = String::new();
+        loop {
+            match self.next() {
+                Some(Token::Char(c)) => name.push(c),
+                Some(Token::Close) => return Ok(name),
+                _ => return Err(anyhow!("environment names can only contain letters")),
+            }
+        }
+    }
+}
+
+fn char_to_mathml(c: char) -> String {
+    match c {
+        c if c.is_alphabetic() => format!("<mi>{c}</mi>"),
+        '-' => String::from("<mo>−</mo>"),
+        '*' => String::from("<mo>∗</mo>"),
+        '\'' => String::from("<mo>′</mo>"),
+        c => format!("<mo>{}</mo>", escape_html(&c.to_string())),
+    }
+}
+
+fn fence(delimiter: &str) -> String {
+    match delimiter.is_empty() {
+        true => String::new(),
+        false => format!("<mo fence=\"true\" stretchy=\"true\">{delimiter}</mo>"),
+    }
+}
+
+fn get_symbol(name: &str) -> Option<(&'static str, SymbolKind)> {
+    use SymbolKind::*;
+
+    let symbol = match name {
+        "alpha" => ("α", Identifier),
+        "beta" => ("β", Identifier),
+        "gamma" => ("γ", Identifier),
+        "delta" => ("δ", Identifier),
+        "epsilon" => ("ϵ", Identifier),
+        "varepsilon" => ("ε", Identifier),
+        "zeta" => ("ζ", Identifier),
+        "eta" => ("η", Identifier),
+        "theta" => ("θ", Identifier),
+        "vartheta" => ("ϑ", Identifier),
+        "iota" => ("ι", Identifier),
+        "kappa" => ("κ", Identifier),
+        "lambda" => ("λ", Identifier),
+        "mu" => ("μ", Identifier),
+        "nu" => ("ν", Identifier),
+        "xi" => ("ξ", Identifier),
+        "pi" => ("π", Identifier),
+        "varpi" => ("ϖ", Identifier),
+        "rho" => ("ρ", Identifier),
+        "varrho" => ("ϱ", Identifier),
+        "sigma" => ("σ", Identifier),
+        "varsigma" => ("ς", Identifier),
+        "tau" => ("τ", Identifier),
+        "upsilon" => ("υ", Identifier),
+        "phi" => ("ϕ", Identifier),
+        "varphi" => ("φ", Identifier),
+        "chi" => ("χ", Identifier),
+        "psi" => ("ψ", Identifier),
+        "omega" => ("ω", Identifier),
+        "Gamma" => ("Γ", Identifier),
+        "Delta" => ("Δ", Identifier),
+        "Theta" => ("Θ", Identifier),
+        "Lambda" => ("Λ", Identifier),
+        "Xi" => ("Ξ", Identifier),
+        "Pi" => ("Π", Identifier),
+        "Sigma" => ("Σ", Identifier),
+        "Upsilon" => ("Υ", Identifier),
+        "Phi" => ("Φ", Identifier),
+        "Psi" => ("Ψ", Identifier),
+        "Omega" => ("Ω", Identifier),
+        "infty" => ("∞", Identifier),
+        "partial" => ("∂", Identifier),
+        "nabla" => ("∇", Identifier),
+        "ell" => ("ℓ", Identifier),
+        "hbar" => ("ℏ", Identifier),
+        "emptyset" | "varnothing" => ("∅", Identifier),
+        "aleph" => ("ℵ", Identifier),
+        "pm" => ("±", Operator),
+        "mp" => ("∓", Operator),
+        "times" => ("×", Operator),
+        "div" => ("÷", Operator),
+        "cdot" => ("⋅", Operator),
+        "ast" => ("∗", Operator),
+        "star" => ("⋆", Operator),
+        "circ" => ("∘", Operator),
+        "bullet" => ("∙", Operator),
+        "oplus" => ("⊕", Operator),
+        "otimes" => ("⊗", Operator),
+        "le" | "leq" => ("≤", Operator),
+        "ge" | "geq" => ("≥", Operator),
+        "ne" | "neq" => ("≠", Operator),
+        "approx" => ("≈", Operator),
+        "sim" => ("∼", Operator),
+        "simeq" => ("≃", Operator),
+        "cong" => ("≅", Operator),
+        "equiv" => ("≡", Operator),
+        "propto" => ("∝", Operator),
+        "ll" => ("≪", Operator),
+        "gg" => ("≫", Operator),
+        "in" => ("∈", Operator),
+        "notin" => ("∉", Operator),
+        "ni" => ("∋", Operator),
+        "subset" => ("⊂", Operator),
+        "supset" => ("⊃", Operator),
+        "subseteq" => ("⊆", Operator),
+        "supseteq" => ("⊇", Operator),
+        "cup" => ("∪", Operator),
+        "cap" => ("∩", Operator),
+        "setminus" => ("∖", Operator),
+        "wedge" | "land" => ("∧", Operator),
+        "vee" | "lor" => ("∨", Operator),
+        "neg" | "lnot" => ("¬", Operator),
+        "forall" => ("∀", Operator),
+        "exists" => ("∃", Operator),
+        "to" | "rightarrow" => ("→", Operator),
+        "leftarrow" | "gets" => ("←", Operator),
+        "leftrightarrow" => ("↔", Operator),
+        "Rightarrow" => ("⇒", Operator),
+        "Leftarrow" => ("⇐", Operator),
+        "Leftrightarrow" | "iff" => ("⇔", Operator),
+        "implies" => ("⟹", Operator),
+        "mapsto" => ("↦", Operator),
+        "ldots" | "dots" => ("…", Operator),
+        "cdots" => ("⋯", Operator),
+        "vdots" => ("⋮", Operator),
+        "ddots" => ("⋱", Operator),
+        "langle" => ("⟨", Operator),
+        "rangle" => ("⟩", Operator),
+        "lfloor" => ("⌊", Operator),
+        "rfloor" => ("⌋", Operator),
+        "lceil" => ("⌈", Operator),
+        "rceil" => ("⌉", Operator),
+        "mid" => ("∣", Operator),
+        "parallel" => ("∥", Operator),
+        "perp" => ("⊥", Operator),
+        "angle" => ("∠", Operator),
+        "prime" => ("′", Operator),
+        "colon" => (":", Operator),
+        "vert" => ("|", Operator),
+        "sum" => ("∑", LargeOperator),
+        "prod" => ("∏", LargeOperator),
+        "coprod" => ("∐", LargeOperator),
+        "bigcup" => ("⋃", LargeOperator),
+        "bigcap" => ("⋂", LargeOperator),
+        "bigoplus" => ("⨁", LargeOperator),
+        "bigotimes" => ("⨂", LargeOperator),
+        "int" => ("∫", Integral),
+        "iint" => ("∬", Integral),
+        "iiint" => ("∭", Integral),
+        "oint" => ("∮", Integral),
+        _ => return None,
+    };
+
+    Some(symbol)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Command(name) => format!("\\{name}"),
+        Token::Text(text) => format!("'{text}'"),
+        Token::Number(number) => format!("'{number}'"),
+        Token::Char(c) => format!("'{c}'"),
+        Token::Open => String::from("'{'"),
+        Token::Close => String::from("'}'"),
+        Token::Superscript => String::from("'^'"),
+        Token::Subscript => String::from("'_'"),
+        Token::NextCell => String::from("'&'"),
+        Token::NextRow => String::from("'\\\\'"),
+    }
+}
+
+/// Escapes text for HTML, including braces so TeX like '{a}}' isn't read as a placeholder.
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('{', "&#123;")
+        .replace('}', "&#125;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::html;
+
+    fn body(tex: &str) -> String {
+        let mathml = tex_to_mathml(tex, false).unwrap();
+        let start = mathml.find("<mrow>").unwrap() + "<mrow>".len();
+        let end = mathml.rfind("</mrow><annotation").unwrap();
+        mathml[start..end].to_string()
+    }
+
+    #[test]
+    fn tex_to_mathml_test() {
+        assert_eq!(
+            body("x^2 + y_1"),
+            "<msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><msub><mi>y</mi><mn>1</mn></msub>"
+        );
+        assert_eq!(
+            body("\\frac{a}{2\\pi}"),
+            "<mfrac><mi>a</mi><mrow><mn>2</mn><mi>π</mi></mrow></mfrac>"
+        );
+        assert_eq!(
+            body("\\sum_{i=0}^n i"),
+            "<munderover><mo largeop=\"true\" movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>0</mn></mrow><mi>n</mi></munderover><mi>i</mi>"
+        );
+        assert_eq!(
+            body("\\sqrt[3]{x}"),
+            "<mroot><mi>x</mi><mrow><mn>3</mn></mrow></mroot>"
+        );
+        assert_eq!(
+            body("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+        );
+        assert_eq!(body("\\text{if } x"), "<mtext>if </mtext><mi>x</mi>");
+
+        let display = tex_to_mathml("x", true).unwrap();
+        assert!(display.starts_with("<math display=\"block\">"));
+        assert!(display.ends_with(
+            "<annotation encoding=\"application/x-tex\">x</annotation></semantics></math>"
+        ));
+
+        assert!(tex_to_mathml("\\frac{a}", false).is_err());
+        assert!(tex_to_mathml("\\unknown", false).is_err());
+        assert!(tex_to_mathml("{x", false).is_err());
+        assert!(tex_to_mathml("\\left( x", false).is_err());
+    }
+
+    #[test]
+    fn extract_math_test() {
+        let input = "Costs $5 and $10, `$x$` and $a_1*b$.\n\n$$\n\\bad{x}\n$$\n";
+        let (output, formulas) = extract_math(input);
+
+        assert_eq!(
+            output,
+            "Costs $5 and $10, `$x$` and \u{E000}0\u{E001}.\n\n\u{E000}1\u{E001}\n"
+        );
+        assert!(formulas[0]
+            .html
+            .contains("<msub><mi>a</mi><mn>1</mn></msub><mo>∗</mo><mi>b</mi>"));
+        assert_eq!(
+            formulas[1].html,
+            "<code class=\"math\">$$\\bad&#123;x&#125;$$</code>"
+        );
+    }
+
+    #[test]
+    fn insert_math_test() {
+        let (input, formulas) = extract_math("*Area* is $\\pi r^2$ here\n");
+        let events = insert_math(Parser::new(&input).collect(), &formulas);
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+
+        assert!(output.starts_with("<p><em>Area</em> is <math><semantics>"));
+        assert!(output.ends_with("</semantics></math> here</p>\n"));
+
+        // Display math is a block of its own instead of being inside a paragraph
+        let (input, formulas) = extract_math("Before\n$$\nx\n$$\n$$y$$\nafter\n\n$$z$$\n");
+        let events = insert_math(Parser::new(&input).collect(), &formulas);
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+
+        let block = |tex: &str| {
+            format!("<math display=\"block\"><semantics><mrow><mi>{tex}</mi></mrow><annotation encoding=\"application/x-tex\">{tex}</annotation></semantics></math>\n")
+        };
+        assert_eq!(
+            output,
+            format!(
+                "<p>Before</p>\n{}{}<p>after</p>\n{}",
+                block("x"),
+                block("y"),
+                block("z")
+            )
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use chrono::DateTime;
+use yaml_rust::Yaml;
+
+use crate::markdown_compiling::Page;
+use crate::site_data::category_ancestors;
+
+/// URL patterns read from the 'permalinks' section of the config, which look like this;
+///
+/// permalinks:
+///   default: /:category/:slug.html
+///   blog: /:year/:month/:slug/
+#[derive(Clone, Debug, Default)]
+pub struct Permalinks {
+    pub default: Option<String>,
+    pub categories: HashMap<String, String>,
+}
+
+impl Permalinks {
+    pub fn from_yaml(yaml: &Yaml) -> Permalinks {
+        let mut permalinks = Permalinks::default();
+
+        if let Some(hash) = yaml.as_hash() {
+            for (key, value) in hash {
+                if let (Some(key), Some(pattern)) = (key.as_str(), value.as_str()) {
+                    match key {
+                        "default" => permalinks.default = Some(pattern.to_string()),
+                        cat => {
+                            let cat = cat.trim_matches('/').to_lowercase();
+                            permalinks.categories.insert(cat, pattern.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        permalinks
+    }
+
+    /// Finds the pattern for a category, falling back to its parent categories and then the
+    /// default pattern.
+    pub fn pattern_for(&self, category: &str) -> Option<&str> {
+        let cat = category.to_lowercase();
+        let mut candidates = category_ancestors(&cat);
+        candidates.push(cat);
+
+        candidates
+            .iter()
+            .rev()
+            .find_map(|c| self.categories.get(c))
+            .or(self.default.as_ref())
+            .map(|pattern| pattern.as_str())
+    }
+
+    /// Creates the URL for a page. Index pages always keep their default location, so the
+    /// navbar links to category indexes keep working.
+    pub fn url_for(&self, page: &Page) -> Result<String> {
+        match self.pattern_for(&page.category) {
+            Some(pattern) if page.filename != "index" => expand_permalink(pattern, page),
+            _ => Ok(get_page_url(&page.output_path, &page.slug)),
+        }
+    }
+}
+
+/// Creates the default URL of a page from its output directory and slug.
+pub fn get_page_url(output_path: &str, slug: &str) -> String {
+    format!("{}/{}.html", output_path.trim_end_matches('/'), slug)
+}
+
+pub fn expand_permalink(pattern: &str, page: &Page) -> Result<String> {
+    let mut url = pattern.to_string();
+
+    // Only patterns which use the date need the page to have one
+    if [":year", ":month", ":day"]
+        .iter()
+        .any(|token| pattern.contains(token))
+    {
+        let date = DateTime::parse_from_rfc3339(&page.date).map_err(|_| {
+            anyhow!(
+                "[ ERROR ] Could not read the date of {} to create its permalink",
+                page.filepath
+            )
+        })?;
+        url = url
+            .replace(":year", &date.format("%Y").to_string())
+            .replace(":month", &date.format("%m").to_string())
+            .replace(":day", &date.format("%d").to_string());
+    }
+
+    let url = url
+        .replace(":slug", &page.slug)
+        .replace(":category", &page.category.to_lowercase())
+        .replace(":filename", &page.filename);
+
+    let mut output = String::from("/");
+    for segment in url.split('/').filter(|s| !s.is_empty()) {
+        output.push_str(segment);
+        output.push('/');
+    }
+    if !url.ends_with('/') {
+        output.pop();
+    }
+
+    Ok(output)
+}
+
+/// Gets the file within 'site/' a URL is served from, using 'index.html' for URLs ending in '/'.
+pub fn get_output_file(url: &str) -> String {
+    if url.ends_with('/') {
+        format!("site{url}index.html")
+    } else {
+        format!("site{url}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn expand_permalink_test() {
+        let mut page: Page = Page::new();
+        page.date = String::from("2021-05-08T15:16:19-07:00");
+        page.filename = String::from("Post Title");
+        page.slug = String::from("post-title");
+        page.category = String::from("Blog");
+
+        let output = expand_permalink("/:year/:month/:slug/", &page).unwrap();
+        assert_eq!(output, "/2021/05/post-title/");
+        let output = expand_permalink(":category/:day/:filename.html", &page).unwrap();
+        assert_eq!(output, "/blog/08/Post Title.html");
+
+        page.category = String::new();
+        let output = expand_permalink("/:category/:slug.html", &page).unwrap();
+        assert_eq!(output, "/post-title.html");
+
+        page.date = String::from("not a date");
+        assert!(expand_permalink("/:year/:slug/", &page).is_err());
+        let output = expand_permalink("/blog/:slug/", &page).unwrap();
+        assert_eq!(output, "/blog/post-title/");
+    }
+
+    #[test]
+    fn pattern_for_test() {
+        let config = "\
+permalinks:
+  default: /:category/:slug.html
+  writing: /:year/:slug/
+";
+        let yaml = &YamlLoader::load_from_str(config).unwrap()[0];
+        let permalinks = Permalinks::from_yaml(&yaml["permalinks"]);
+
+        assert_eq!(permalinks.pattern_for("writing"), Some("/:year/:slug/"));
+        assert_eq!(
+            permalinks.pattern_for("writing/fiction"),
+            Some("/:year/:slug/")
+        );
+        assert_eq!(
+            permalinks.pattern_for("blog"),
+            Some("/:category/:slug.html")
+        );
+        assert_eq!(Permalinks::default().pattern_for("blog"), None);
+    }
+
+    #[test]
+    fn get_output_file_test() {
+        assert_eq!(get_output_file("/blog/post.html"), "site/blog/post.html");
+        assert_eq!(
+            get_output_file("/2021/05/post/"),
+            "site/2021/05/post/index.html"
+        );
+        assert_eq!(get_page_url("/", "index"), "/index.html");
+        assert_eq!(get_page_url("/blog", "post"), "/blog/post.html");
+    }
+}
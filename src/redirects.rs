@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::directory_handling::write_site_file;
+use crate::permalinks::get_output_file;
+use crate::site_data::Site;
+
+/// Writes a redirect page for every alias in the frontmatter of each page, and a '_redirects'
+/// file listing all of them if 'redirects_file' is enabled in the config.
+pub fn write_redirects(site: &Site) -> Result<()> {
+    let mut redirects_file: String = String::new();
+    // The page each alias has already been written for
+    let mut written: HashMap<String, &str> = HashMap::new();
+
+    for page in &site.pages {
+        for alias in &page.aliases {
+            let alias_url =
+                get_alias_url(alias).map_err(|e| anyhow!("{e} in {}", page.filepath))?;
+
+            if site.directory.contains(&alias_url) {
+                println!(
+                    "[ WARN ] Alias {alias} of {} is already used by another page, skipping",
+                    page.filepath
+                );
+                continue;
+            }
+            if let Some(other) = written.get(&alias_url) {
+                println!(
+                    "[ WARN ] Alias {alias} of {} is already an alias of {other}, skipping",
+                    page.filepath
+                );
+                continue;
+            }
+            written.insert(alias_url.to_string(), &page.filepath);
+
+            let target = site.absolute_url(&page.url);
+            write_site_file(&get_output_file(&alias_url), &create_redirect_page(&target))?;
+            redirects_file.push_str(&format!("{} {} 301\n", normalise_alias(alias), page.url));
+        }
+    }
+
+    if site.redirects_file && !redirects_file.is_empty() {
+        write_site_file("site/_redirects", &redirects_file)?;
+    }
+
+    Ok(())
+}
+
+/// Makes sure an alias starts with a '/', since it is always relative to the site root.
+fn normalise_alias(alias: &str) -> String {
+    format!("/{}", alias.trim().trim_start_matches('/'))
+}
+
+/// Gets the URL the redirect page of an alias is written to. Aliases without a '.html' file are
+/// treated as folders, so '/blog/old-post' is written to '/blog/old-post/index.html'. Aliases
+/// with '..' in their path are an error, since they could be written outside of 'site/'.
+pub fn get_alias_url(alias: &str) -> Result<String> {
+    let alias = normalise_alias(alias);
+
+    if alias.split(['/', '\\']).any(|segment| segment == "..") {
+        return Err(anyhow!(
+            "[ ERROR ] Alias {alias} can't use '..' to go up a folder"
+        ));
+    }
+
+    if alias.ends_with(".html") || alias.ends_with('/') {
+        Ok(alias)
+    } else {
+        Ok(format!("{alias}/"))
+    }
+}
+
+pub fn create_redirect_page(target: &str) -> String {
+    format!(
+        "\
+<!doctype html>
+<html lang=\"en\">
+<head>
+  <meta charset=\"utf-8\">
+  <title>Redirecting to {target}</title>
+  <link rel=\"canonical\" href=\"{target}\">
+  <meta name=\"robots\" content=\"noindex\">
+  <meta http-equiv=\"refresh\" content=\"0; url={target}\">
+</head>
+<body>
+  <p>This page has moved to <a href=\"{target}\">{target}</a>.</p>
+</body>
+</html>
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_alias_url_test() {
+        let url = |alias| get_alias_url(alias).unwrap();
+        assert_eq!(url("/blog/old-post.html"), "/blog/old-post.html");
+        assert_eq!(url("blog/old-post.html"), "/blog/old-post.html");
+        assert_eq!(url("/2021/05/old-post/"), "/2021/05/old-post/");
+        assert_eq!(url("/blog/old-post"), "/blog/old-post/");
+        assert!(get_alias_url("../../../tmp/escaped").is_err());
+        assert!(get_alias_url("/blog/..\\..\\escaped").is_err());
+    }
+
+    #[test]
+    fn create_redirect_page_test() {
+        let output = create_redirect_page("https://www.example.com/blog/post.html");
+        assert!(output
+            .contains("<link rel=\"canonical\" href=\"https://www.example.com/blog/post.html\">"));
+        assert!(output.contains(
+            "<meta http-equiv=\"refresh\" content=\"0; url=https://www.example.com/blog/post.html\">"
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use yaml_rust::Yaml;
+
+use crate::directory_handling::write_site_file;
+use crate::markdown_compiling::{markdown_to_text, Page};
+use crate::site_data::{parse_date, Site};
+
+const SEARCH_FIELDS: [&str; 6] = ["title", "url", "category", "date", "description", "body"];
+
+/// Options read from the 'search' section of the config
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    pub enabled: bool,
+    pub fields: Vec<String>,
+    pub body_length: Option<usize>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            enabled: false,
+            fields: SEARCH_FIELDS.iter().map(|f| f.to_string()).collect(),
+            body_length: None,
+        }
+    }
+}
+
+impl SearchConfig {
+    pub fn from_yaml(yaml: &Yaml) -> SearchConfig {
+        let default = SearchConfig::default();
+
+        if let Some(enabled) = yaml.as_bool() {
+            return SearchConfig { enabled, ..default };
+        }
+        if yaml.as_hash().is_none() {
+            return default;
+        }
+
+        let fields: Vec<String> = match yaml["fields"].as_vec() {
+            Some(fields) => fields
+                .iter()
+                .filter_map(|field| field.as_str())
+                .filter(|field| {
+                    let known = SEARCH_FIELDS.contains(field);
+                    if !known {
+                        println!("[ WARN ] Unknown search field {field}, skipping");
+                    }
+                    known
+                })
+                .map(String::from)
+                .collect(),
+            None => default.fields,
+        };
+
+        SearchConfig {
+            enabled: yaml["enabled"].as_bool().unwrap_or(true),
+            fields,
+            body_length: yaml["body_length"]
+                .as_i64()
+                .map(|length| length.max(0) as usize),
+        }
+    }
+}
+
+/// Writes 'site/search.json', which lists every page that isn't an index page or 'noindex' so a
+/// theme can search the site without a server.
+pub fn write_search_index(site: &Site) -> Result<()> {
+    if !site.search.enabled {
+        return Ok(());
+    }
+
+    write_site_file("site/search.json", &create_search_index(site))
+}
+
+pub fn create_search_index(site: &Site) -> String {
+    let mut pages: Vec<&Page> = site
+        .pages
+        .iter()
+        .filter(|page| !page.noindex && page.filename != "index")
+        .collect();
+    pages.sort_by(|a, b| a.url.cmp(&b.url));
+
+    let entries: Vec<Value> = pages
+        .iter()
+        .map(|page| create_search_entry(page, &site.search))
+        .collect();
+
+    // Serialising a 'Value' can't fail, since all of its keys are strings
+    serde_json::to_string(&entries).unwrap_or_default()
+}
+
+fn create_search_entry(page: &Page, config: &SearchConfig) -> Value {
+    let mut entry: Map<String, Value> = Map::new();
+
+    for field in &config.fields {
+        let value = match field.as_str() {
+            "title" => json!(page.title),
+            "url" => json!(page.url),
+            "category" => json!(page.category),
+            "date" => match parse_date(&page.date) {
+                Some(date) => json!(date.to_rfc3339()),
+                None => Value::Null,
+            },
+            "description" => json!(page.description),
+            "body" => json!(truncate_text(
+                &markdown_to_text(&page.markdown),
+                config.body_length
+            )),
+            _ => continue,
+        };
+        entry.insert(field.to_string(), value);
+    }
+
+    Value::Object(entry)
+}
+
+/// Shortens text to at most `length` characters, cutting at the end of a word where it can.
+pub fn truncate_text(text: &str, length: Option<usize>) -> String {
+    let length = match length {
+        Some(length) if text.chars().count() > length => length,
+        _ => return text.to_string(),
+    };
+
+    let cut: String = text.chars().take(length).collect();
+    let ends_on_word = text.chars().nth(length).map_or(true, char::is_whitespace);
+    match cut.rfind(' ') {
+        Some(i) if i > 0 && !ends_on_word => cut[..i].to_string(),
+        _ => cut,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_text_test() {
+        assert_eq!(truncate_text("one two three", None), "one two three");
+        assert_eq!(truncate_text("one two three", Some(20)), "one two three");
+        assert_eq!(truncate_text("one two three", Some(9)), "one two");
+        assert_eq!(truncate_text("one two three", Some(7)), "one two");
+        assert_eq!(truncate_text("onetwothree", Some(6)), "onetwo");
+    }
+
+    #[test]
+    fn create_search_index_test() {
+        let mut site: Site = Site::default();
+        site.search.fields = vec![String::from("title"), String::from("body")];
+        site.search.body_length = Some(12);
+
+        let mut page: Page = Page::new();
+        page.title = String::from("A Post");
+        page.filename = String::from("a-post");
+        page.markdown = String::from("# Heading\n\nSome *styled* text and [a link](/).\n");
+        site.pages.push(page);
+
+        let output = create_search_index(&site);
+        assert_eq!(output, "[{\"body\":\"Heading Some\",\"title\":\"A Post\"}]");
+    }
+}
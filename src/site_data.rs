@@ -1,12 +1,28 @@
 use anyhow::{anyhow, Result};
+use std::cmp::Reverse;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 
-use chrono::DateTime;
+use chrono::{DateTime, FixedOffset};
 use yaml_rust::YamlLoader;
 
+use crate::base_url::BaseUrl;
+use crate::callouts::CalloutConfig;
+use crate::feeds::FeedConfig;
+use crate::headings::TocConfig;
+use crate::highlighting::Highlighter;
+use crate::images::ImageConfig;
+use crate::links::{BrokenLinks, ExternalLinkConfig};
 use crate::markdown_compiling::Page;
+use crate::markdown_options::MarkdownOptions;
+use crate::permalinks::Permalinks;
+use crate::search::SearchConfig;
+use crate::sitemap::RobotsConfig;
+use crate::summaries::IndexConfig;
+
+/// Categories which don't get an index page or a link in the navbar
+const IGNORED_CATEGORIES: [&str; 4] = ["home", "index", "draft", ""];
 
 pub struct Site {
     pub pages: Vec<Page>,
@@ -16,12 +32,32 @@ pub struct Site {
     pub static_url: String,
     pub template_url: String,
     pub template_path: String,
-    pub base_url: String,
+    pub base_url: BaseUrl,
+    pub mirror_content_paths: bool,
+    pub permalinks: Permalinks,
+    pub slug_from_title: bool,
+    pub redirects_file: bool,
+    pub feeds: FeedConfig,
+    pub sitemap: bool,
+    pub robots: Option<RobotsConfig>,
+    pub author: String,
+    pub default_image: String,
+    pub search: SearchConfig,
+    pub toc: TocConfig,
+    pub markdown: MarkdownOptions,
+    pub highlight: Option<Highlighter>,
+    pub callouts: CalloutConfig,
+    pub broken_links: BrokenLinks,
+    pub external_links: ExternalLinkConfig,
+    pub images: ImageConfig,
+    pub check_links: bool,
+    pub words_per_minute: usize,
+    pub category_index: IndexConfig,
 }
 
-impl Site {
-    pub fn new() -> Result<Site> {
-        let mut site = Site {
+impl Default for Site {
+    fn default() -> Site {
+        Site {
             pages: Vec::new(),
             directory: Vec::new(),
             categories: HashSet::new(),
@@ -31,8 +67,34 @@ impl Site {
             ),
             template_url: String::from("https://raw.githubusercontent.com/SoftAnnaLee/md_puppy/main/template/boilerplate.html"),
             template_path: String::from("template/boilerplate.html"),
-            base_url: String::from("https://www.example.com"),
-        };
+            base_url: BaseUrl::default(),
+            mirror_content_paths: false,
+            permalinks: Permalinks::default(),
+            slug_from_title: false,
+            redirects_file: false,
+            feeds: FeedConfig::default(),
+            sitemap: true,
+            robots: None,
+            author: String::new(),
+            default_image: String::new(),
+            search: SearchConfig::default(),
+            toc: TocConfig::default(),
+            markdown: MarkdownOptions::default(),
+            highlight: None,
+            callouts: CalloutConfig::default(),
+            broken_links: BrokenLinks::Warn,
+            external_links: ExternalLinkConfig::default(),
+            images: ImageConfig::default(),
+            check_links: false,
+            words_per_minute: 200,
+            category_index: IndexConfig::default(),
+        }
+    }
+}
+
+impl Site {
+    pub fn new() -> Result<Site> {
+        let mut site = Site::default();
 
         site.parse_config()?;
 
@@ -61,10 +123,45 @@ impl Site {
                     .as_str()
                     .unwrap_or("https://raw.githubusercontent.com/SoftAnnaLee/md_puppy/main/template/boilerplate.html")
                     .to_string();
-                self.base_url = fm["base_url"]
-                    .as_str()
-                    .unwrap_or("https://www.example.com")
-                    .to_string();
+                self.base_url = match fm["base_url"].as_str() {
+                    Some(url) => BaseUrl::parse(url)?,
+                    None => BaseUrl::default(),
+                };
+                if self.base_url.has_path() {
+                    println!(
+                        "[ WARN ] base_url {} has a path, but links between pages are written from the root of the domain, so the site needs to be served from there",
+                        self.base_url
+                    );
+                }
+                self.mirror_content_paths = fm["mirror_content_paths"].as_bool().unwrap_or(false);
+                self.permalinks = Permalinks::from_yaml(&fm["permalinks"]);
+                self.slug_from_title = fm["slug_from_title"].as_bool().unwrap_or(false);
+                self.redirects_file = fm["redirects_file"].as_bool().unwrap_or(false);
+                self.feeds = FeedConfig::from_yaml(&fm["feeds"]);
+                self.sitemap = fm["sitemap"].as_bool().unwrap_or(true);
+                self.robots = RobotsConfig::from_yaml(&fm["robots"]);
+                self.author = fm["author"].as_str().unwrap_or("").to_string();
+                self.default_image = fm["default_image"].as_str().unwrap_or("").to_string();
+                self.search = SearchConfig::from_yaml(&fm["search"]);
+                self.toc = TocConfig::from_yaml(&fm["toc"]);
+                // 'math' and 'heading_anchors' were set on their own before the markdown section
+                let defaults = MarkdownOptions {
+                    math: fm["math"].as_bool().unwrap_or(false),
+                    heading_anchors: fm["heading_anchors"].as_bool().unwrap_or(false),
+                    ..MarkdownOptions::default()
+                };
+                self.markdown = MarkdownOptions::from_yaml(&fm["markdown"], &defaults);
+                self.highlight = Highlighter::from_yaml(&fm["highlight"]);
+                self.callouts = CalloutConfig::from_yaml(&fm["callouts"]);
+                self.broken_links = BrokenLinks::from_yaml(&fm["broken_links"]);
+                self.external_links = ExternalLinkConfig::from_yaml(&fm["external_links"]);
+                self.images = ImageConfig::from_yaml(&fm["images"]);
+                self.check_links = fm["check_links"].as_bool().unwrap_or(false);
+                self.words_per_minute = fm["words_per_minute"]
+                    .as_i64()
+                    .filter(|&words| words > 0)
+                    .unwrap_or(200) as usize;
+                self.category_index = IndexConfig::from_yaml(&fm["category_index"]);
 
                 Ok(())
             }
@@ -72,12 +169,22 @@ impl Site {
     }
 
     pub fn add_page(&mut self, filepath: &str) -> Result<()> {
-        let page: Page = Page::from_file(filepath)?;
+        let page: Page = Page::from_file(filepath, self)?;
         let cat: &str = &page.category.to_lowercase();
-        let path = format!("{}/{}.html", page.output_path, page.filename);
+        let path = page.url.to_string();
 
-        let ignored_categories = ["home", "index", "draft", ""];
-        if !ignored_categories.contains(&cat) {
+        if let Some(other) = self.pages.iter().find(|p| p.url == path) {
+            return Err(anyhow!(
+                "[ ERROR ] {} and {} would both be written to {path}, give one of them a different 'slug'",
+                other.filepath,
+                filepath
+            ));
+        }
+
+        if !IGNORED_CATEGORIES.contains(&cat) {
+            for parent in category_ancestors(cat) {
+                self.categories.insert(parent);
+            }
             self.categories.insert(cat.to_string());
         }
 
@@ -89,36 +196,195 @@ impl Site {
         Ok(())
     }
 
+    /// Renders every page, which is done once they have all been added so each page can link to
+    /// any of the others.
+    pub fn render_pages(&mut self) -> Result<()> {
+        let mut pages: Vec<Page> = self.pages.to_vec();
+
+        for page in &mut pages {
+            page.content_to_html(self)?;
+        }
+        self.pages = pages;
+
+        Ok(())
+    }
+
+    /// Turns a path on the site into a full URL using the base url.
+    pub fn absolute_url(&self, path: &str) -> String {
+        self.base_url.join(path)
+    }
+
+    /// Gets the pages which belong in feeds, newest first. Index pages, 'noindex' pages and pages
+    /// without a category are left out, and giving a category only returns pages within it.
+    pub fn feed_pages(&self, category: Option<&str>) -> Vec<&Page> {
+        let mut pages: Vec<&Page> = self
+            .pages
+            .iter()
+            .filter(|page| {
+                let cat = page.category.to_lowercase();
+                let in_category = match category {
+                    Some(c) => cat == c || cat.starts_with(&format!("{c}/")),
+                    None => true,
+                };
+                in_category && !page.noindex && is_post(page)
+            })
+            .collect();
+
+        pages.sort_by_key(|page| Reverse(parse_date(&page.date)));
+        pages
+    }
+
     pub fn create_category_links(&mut self) -> String {
         let mut output: String = String::from("<li><a href='/index.html'>Home</a></li>\n");
+        output.push_str(&self.create_child_links(None));
+        output
+    }
 
-        let mut categories: Vec<String> = self.categories.iter().map(String::from).collect();
-        categories.sort();
+    /// Creates the nav links for every category directly below `parent`, recursing into their
+    /// own children so nested categories end up as nested lists.
+    fn create_child_links(&self, parent: Option<&str>) -> String {
+        let mut output: String = String::new();
 
-        for cat in categories {
-            output.push_str(&format!("<li><a href='/{cat}/index.html'>{cat}</a></li>\n"));
+        for cat in self.child_categories(parent) {
+            let name = category_name(&cat);
+            output.push_str(&format!("<li><a href='/{cat}/index.html'>{name}</a>"));
+
+            let children = self.create_child_links(Some(&cat));
+            if !children.is_empty() {
+                output.push_str(&format!("\n<ul>\n{children}</ul>\n"));
+            }
+
+            output.push_str("</li>\n");
         }
 
         output
     }
 
+    /// Returns the sorted categories whose parent is `parent`, or the top level categories when
+    /// `parent` is `None`.
+    pub fn child_categories(&self, parent: Option<&str>) -> Vec<String> {
+        let mut children: Vec<String> = self
+            .categories
+            .iter()
+            .filter(|cat| category_parent(cat) == parent)
+            .map(String::from)
+            .collect();
+        children.sort();
+        children
+    }
+
     pub fn create_category_index(&mut self, category: &str) -> String {
-        let mut output: String = String::from("<ul>\n");
+        let mut output: String = String::new();
+
+        let children = self.child_categories(Some(category));
+        if !children.is_empty() {
+            output.push_str("<ul class='subcategories'>\n");
+            for child in children {
+                let name = category_name(&child);
+                output.push_str(&format!(
+                    "<li><a href='/{child}/index.html'>{name}</a></li>\n"
+                ));
+            }
+            output.push_str("</ul>\n");
+        }
+
+        output.push_str("<ul>\n");
 
         self.pages.sort_by(|a, b| b.cmp(a));
 
         for page in &self.pages {
             if page.category == category && !page.filepath.ends_with("index.md") {
-                let path = format!("{}/{}.html", &page.output_path, &page.filename);
+                let path = &page.url;
                 let date = convert_datetime(&page.date);
                 let title = &page.title;
-                output.push_str(&format!("<li><a href='{path}'>{date} - {title}</a></li>\n",));
+                output.push_str(&format!("<li><a href='{path}'>{date} - {title}</a>"));
+                output.push_str(&self.create_index_details(page));
+                output.push_str("</li>\n");
             }
         }
 
         output.push_str("</ul>\n");
         output
     }
+
+    /// Creates the word count, reading time and summary shown after a page's link in an index,
+    /// depending on which are turned on in the 'category_index' section of the config.
+    fn create_index_details(&self, page: &Page) -> String {
+        let mut output: String = String::new();
+
+        if self.category_index.word_count {
+            let words = page.word_count;
+            output.push_str(&format!(" <span class='word-count'>{words} words</span>"));
+        }
+        if self.category_index.reading_time {
+            let minutes = page.reading_time(self);
+            output.push_str(&format!(
+                " <span class='reading-time'>{minutes} min read</span>"
+            ));
+        }
+        if self.category_index.summary && !page.summary.is_empty() {
+            let summary = &page.summary;
+            output.push_str(&format!("\n<div class='summary'>\n{summary}</div>\n"));
+        }
+
+        output
+    }
+}
+
+/// Checks if a page is a post within a category, rather than an index page or a page without a
+/// category.
+pub fn is_post(page: &Page) -> bool {
+    let cat = page.category.to_lowercase();
+    page.filename != "index" && !IGNORED_CATEGORIES.contains(&cat.as_str())
+}
+
+/// Returns the category a nested category belongs to, e.g. `writing` for `writing/fiction`.
+pub fn category_parent(category: &str) -> Option<&str> {
+    category.rfind('/').map(|i| &category[..i])
+}
+
+/// Returns every parent of a nested category, starting from the top level.
+pub fn category_ancestors(category: &str) -> Vec<String> {
+    let mut ancestors: Vec<String> = Vec::new();
+    let mut current = category;
+
+    while let Some(parent) = category_parent(current) {
+        ancestors.insert(0, parent.to_string());
+        current = parent;
+    }
+
+    ancestors
+}
+
+/// Returns the name used when displaying a category, which is its last path segment.
+pub fn category_name(category: &str) -> &str {
+    category.rsplit('/').next().unwrap_or(category)
+}
+
+/// Creates a list of links from the home page down through every parent of the category.
+pub fn create_breadcrumbs(category: &str) -> String {
+    let mut output: String = String::from("<li><a href='/index.html'>Home</a></li>\n");
+    let cat = category.to_lowercase();
+
+    if IGNORED_CATEGORIES.contains(&cat.as_str()) {
+        return output;
+    }
+
+    let mut crumbs = category_ancestors(&cat);
+    crumbs.push(cat.to_string());
+
+    for crumb in crumbs {
+        let name = category_name(&crumb);
+        output.push_str(&format!(
+            "<li><a href='/{crumb}/index.html'>{name}</a></li>\n"
+        ));
+    }
+
+    output
+}
+
+pub fn parse_date(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(timestamp).ok()
 }
 
 pub fn convert_datetime(timestamp: &str) -> String {
@@ -136,4 +402,49 @@ mod tests {
         let output = "February  8, 2022 |  3:16 pm".to_string();
         assert_eq!(convert_datetime(input), output);
     }
+
+    #[test]
+    fn category_ancestors_test() {
+        assert_eq!(category_parent("writing/fiction"), Some("writing"));
+        assert_eq!(category_parent("writing"), None);
+        assert_eq!(
+            category_ancestors("writing/fiction/short"),
+            vec!["writing", "writing/fiction"]
+        );
+        assert!(category_ancestors("writing").is_empty());
+        assert_eq!(category_name("writing/fiction"), "fiction");
+    }
+
+    #[test]
+    fn create_index_details_test() {
+        let mut site = Site::default();
+        let mut page = Page::new();
+        page.word_count = 450;
+        page.summary = String::from("<p>The start.</p>\n");
+        assert_eq!(site.create_index_details(&page), "");
+
+        site.category_index = IndexConfig {
+            summary: true,
+            word_count: true,
+            reading_time: true,
+        };
+        assert_eq!(
+            site.create_index_details(&page),
+            " <span class='word-count'>450 words</span> <span class='reading-time'>3 min read</span>\n<div class='summary'>\n<p>The start.</p>\n</div>\n"
+        );
+    }
+
+    #[test]
+    fn create_breadcrumbs_test() {
+        let output = "\
+<li><a href='/index.html'>Home</a></li>
+<li><a href='/writing/index.html'>writing</a></li>
+<li><a href='/writing/fiction/index.html'>fiction</a></li>
+";
+        assert_eq!(create_breadcrumbs("writing/fiction"), output);
+        assert_eq!(
+            create_breadcrumbs("home"),
+            "<li><a href='/index.html'>Home</a></li>\n"
+        );
+    }
 }
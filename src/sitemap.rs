@@ -0,0 +1,175 @@
+use anyhow::Result;
+use yaml_rust::Yaml;
+
+use crate::directory_handling::write_site_file;
+use crate::feeds::escape_xml;
+use crate::markdown_compiling::Page;
+use crate::site_data::{parse_date, Site};
+
+/// Rules read from the 'robots' section of the config
+#[derive(Clone, Debug)]
+pub struct RobotsConfig {
+    pub user_agent: String,
+    pub allow: Vec<String>,
+    pub disallow: Vec<String>,
+}
+
+impl RobotsConfig {
+    /// Reads the rules for 'robots.txt', which is only written if the config has a 'robots'
+    /// section so one copied over from 'static/' isn't replaced.
+    pub fn from_yaml(yaml: &Yaml) -> Option<RobotsConfig> {
+        if yaml.is_badvalue() || yaml.is_null() || yaml.as_bool() == Some(false) {
+            return None;
+        }
+
+        let get_rules = |key: &str| -> Vec<String> {
+            match yaml[key].as_vec() {
+                Some(rules) => rules
+                    .iter()
+                    .filter_map(|rule| rule.as_str())
+                    .map(String::from)
+                    .collect(),
+                None => yaml[key].as_str().map(String::from).into_iter().collect(),
+            }
+        };
+
+        Some(RobotsConfig {
+            user_agent: yaml["user_agent"].as_str().unwrap_or("*").to_string(),
+            allow: get_rules("allow"),
+            disallow: get_rules("disallow"),
+        })
+    }
+}
+
+pub fn write_robots(site: &Site) -> Result<()> {
+    match &site.robots {
+        Some(robots) => write_site_file("site/robots.txt", &create_robots(site, robots)),
+        None => Ok(()),
+    }
+}
+
+pub fn create_robots(site: &Site, robots: &RobotsConfig) -> String {
+    let mut output: String = format!("User-agent: {}\n", robots.user_agent);
+
+    for rule in &robots.allow {
+        output.push_str(&format!("Allow: {rule}\n"));
+    }
+    for rule in &robots.disallow {
+        output.push_str(&format!("Disallow: {rule}\n"));
+    }
+    if robots.allow.is_empty() && robots.disallow.is_empty() {
+        output.push_str("Disallow:\n");
+    }
+
+    if site.sitemap {
+        output.push_str(&format!(
+            "\nSitemap: {}\n",
+            site.absolute_url("/sitemap.xml")
+        ));
+    }
+
+    output
+}
+
+/// Writes 'site/sitemap.xml' with every page which hasn't opted out using 'sitemap: false' or
+/// 'noindex: true' in its frontmatter.
+pub fn write_sitemap(site: &Site) -> Result<()> {
+    if !site.sitemap {
+        return Ok(());
+    }
+
+    write_site_file("site/sitemap.xml", &create_sitemap(site))
+}
+
+pub fn create_sitemap(site: &Site) -> String {
+    let mut pages: Vec<&Page> = site
+        .pages
+        .iter()
+        .filter(|page| page.sitemap && !page.noindex)
+        .collect();
+    pages.sort_by(|a, b| a.url.cmp(&b.url));
+
+    let mut output: String = String::from(
+        "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">
+",
+    );
+
+    for page in pages {
+        output.push_str(&format!(
+            "<url>\n<loc>{}</loc>\n",
+            escape_xml(&site.absolute_url(&page.url))
+        ));
+
+        let lastmod = if page.updated.is_empty() {
+            &page.date
+        } else {
+            &page.updated
+        };
+        if let Some(date) = parse_date(lastmod) {
+            output.push_str(&format!("<lastmod>{}</lastmod>\n", date.to_rfc3339()));
+        }
+
+        output.push_str("</url>\n");
+    }
+
+    output.push_str("</urlset>\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn create_sitemap_test() {
+        let mut site: Site = Site::default();
+
+        let mut page: Page = Page::new();
+        page.url = String::from("/blog/a-post.html");
+        page.date = String::from("2021-05-08T15:16:19-07:00");
+        page.updated = String::from("2022-01-02T03:04:05-07:00");
+        site.pages.push(page);
+
+        let mut page: Page = Page::new();
+        page.url = String::from("/secret.html");
+        page.sitemap = false;
+        site.pages.push(page);
+
+        let mut page: Page = Page::new();
+        page.url = String::from("/unlisted.html");
+        page.noindex = true;
+        site.pages.push(page);
+
+        let output = create_sitemap(&site);
+        assert!(output.contains("<loc>https://www.example.com/blog/a-post.html</loc>"));
+        assert!(output.contains("<lastmod>2022-01-02T03:04:05-07:00</lastmod>"));
+        assert!(!output.contains("secret"));
+        assert!(!output.contains("unlisted"));
+    }
+
+    #[test]
+    fn create_robots_test() {
+        let site: Site = Site::default();
+        let config = "\
+robots:
+  disallow:
+    - /drafts/
+    - /private/
+";
+        let yaml = &YamlLoader::load_from_str(config).unwrap()[0];
+        let robots = RobotsConfig::from_yaml(&yaml["robots"]).unwrap();
+
+        let output = "\
+User-agent: *
+Disallow: /drafts/
+Disallow: /private/
+
+Sitemap: https://www.example.com/sitemap.xml
+";
+        assert_eq!(create_robots(&site, &robots), output);
+        assert!(RobotsConfig::from_yaml(&yaml["missing"]).is_none());
+    }
+}
@@ -0,0 +1,131 @@
+use serde_json::{json, Value};
+
+use crate::markdown_compiling::Page;
+use crate::site_data::{is_post, parse_date, Site};
+
+/// Gets the full URL of the image used in link previews for a page, from the 'image' in its
+/// frontmatter or the 'default_image' in the config. Images without a leading '/' are found
+/// next to the page.
+pub fn get_image_url(page: &Page, site: &Site) -> String {
+    let image = match page.image.is_empty() {
+        true => &site.default_image,
+        false => &page.image,
+    };
+
+    if image.is_empty() || image.starts_with("http://") || image.starts_with("https://") {
+        image.to_string()
+    } else if image.starts_with('/') {
+        site.absolute_url(image)
+    } else {
+        let page_dir = &page.url[..page.url.rfind('/').unwrap_or(0)];
+        site.absolute_url(&format!("{page_dir}/{image}"))
+    }
+}
+
+/// Creates a schema.org JSON-LD script describing a page, as a 'BlogPosting' for pages within a
+/// category and a 'WebPage' for everything else.
+pub fn create_structured_data(page: &Page, site: &Site) -> String {
+    let url = site.absolute_url(&page.url);
+    let is_post = is_post(page);
+
+    let mut data: Value = json!({
+        "@context": "https://schema.org",
+        "@type": if is_post { "BlogPosting" } else { "WebPage" },
+        "url": url,
+        "description": page.description,
+    });
+
+    if is_post {
+        data["headline"] = json!(page.title);
+        data["mainEntityOfPage"] = json!(url);
+        data["publisher"] = json!({ "@type": "Organization", "name": site.site_name });
+    } else {
+        data["name"] = json!(page.title);
+    }
+
+    if let Some(date) = parse_date(&page.date) {
+        data["datePublished"] = json!(date.to_rfc3339());
+        let updated = parse_date(&page.updated).unwrap_or(date);
+        data["dateModified"] = json!(updated.to_rfc3339());
+    }
+
+    let author = match page.author.is_empty() {
+        true => &site.author,
+        false => &page.author,
+    };
+    if !author.is_empty() {
+        data["author"] = json!({ "@type": "Person", "name": author });
+    }
+
+    let image = get_image_url(page, site);
+    if !image.is_empty() {
+        data["image"] = json!(image);
+    }
+
+    // Pretty printing keeps '}}' out of the output, which would be read as a placeholder, and
+    // escaping '</' stops text in the page from closing the script tag early.
+    let json = serde_json::to_string_pretty(&data)
+        .unwrap_or_default()
+        .replace("</", "<\\/");
+
+    format!("<script type=\"application/ld+json\">\n{json}\n</script>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_image_url_test() {
+        let mut site: Site = Site::default();
+        let mut page: Page = Page::new();
+        page.url = String::from("/blog/a-post.html");
+        assert_eq!(get_image_url(&page, &site), "");
+
+        site.default_image = String::from("/img/default.png");
+        assert_eq!(
+            get_image_url(&page, &site),
+            "https://www.example.com/img/default.png"
+        );
+
+        page.image = String::from("cover.png");
+        assert_eq!(
+            get_image_url(&page, &site),
+            "https://www.example.com/blog/cover.png"
+        );
+
+        page.image = String::from("https://cdn.example.com/cover.png");
+        assert_eq!(
+            get_image_url(&page, &site),
+            "https://cdn.example.com/cover.png"
+        );
+    }
+
+    #[test]
+    fn create_structured_data_test() {
+        let site: Site = Site {
+            author: String::from("AnnaLee"),
+            ..Site::default()
+        };
+
+        let mut page: Page = Page::new();
+        page.title = String::from("A Post");
+        page.category = String::from("blog");
+        page.filename = String::from("a-post");
+        page.url = String::from("/blog/a-post.html");
+        page.date = String::from("2021-05-08T15:16:19-07:00");
+
+        let output = create_structured_data(&page, &site);
+        let json = output
+            .strip_prefix("<script type=\"application/ld+json\">\n")
+            .and_then(|json| json.strip_suffix("\n</script>"))
+            .unwrap();
+        let data: Value = serde_json::from_str(json).unwrap();
+
+        assert_eq!(data["@type"], "BlogPosting");
+        assert_eq!(data["headline"], "A Post");
+        assert_eq!(data["datePublished"], "2021-05-08T15:16:19-07:00");
+        assert_eq!(data["author"]["name"], "AnnaLee");
+        assert!(!output.contains("}}"));
+    }
+}
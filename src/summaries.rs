@@ -0,0 +1,249 @@
+use pulldown_cmark::{html, Event, HeadingLevel, Tag};
+use yaml_rust::Yaml;
+
+use crate::markdown_compiling::remove_placeholders;
+
+/// Extra details shown for each page in category index pages, read from the 'category_index'
+/// section of the config.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexConfig {
+    pub summary: bool,
+    pub word_count: bool,
+    pub reading_time: bool,
+}
+
+impl IndexConfig {
+    pub fn from_yaml(yaml: &Yaml) -> IndexConfig {
+        IndexConfig {
+            summary: yaml["summary"].as_bool().unwrap_or(false),
+            word_count: yaml["word_count"].as_bool().unwrap_or(false),
+            reading_time: yaml["reading_time"].as_bool().unwrap_or(false),
+        }
+    }
+}
+
+/// The start of a page, as html and as plain text
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub html: String,
+    pub text: String,
+}
+
+/// Gets everything before a '<!-- more -->' marker, or the first paragraph when there isn't
+/// one, leaving out the page's title. Paragraphs holding only a placeholder, like '{{ toc }}',
+/// are skipped.
+pub fn create_summary(events: &[Event]) -> Summary {
+    let events = skip_title(events);
+    let summary: Vec<Event> = match find_more_marker(events) {
+        Some(end) => remove_placeholder_paragraphs(&events[..end]),
+        None => find_first_paragraph(events).unwrap_or(&[]).to_vec(),
+    };
+
+    let mut output = Summary::default();
+    html::push_html(&mut output.html, summary.iter().cloned());
+    for event in &summary {
+        match event {
+            Event::Text(t) | Event::Code(t) => output.text.push_str(t),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => output.text.push(' '),
+            _ => (),
+        }
+    }
+    output.text = remove_placeholders(&output.text);
+
+    output
+}
+
+/// Works out how many minutes it takes to read some words, rounding up to at least a minute.
+pub fn reading_time(word_count: usize, words_per_minute: usize) -> usize {
+    let words_per_minute = words_per_minute.max(1);
+    let minutes = (word_count + words_per_minute - 1) / words_per_minute;
+    minutes.max(1)
+}
+
+/// Gets the events after a '# Title' heading at the start of the page, which has either been
+/// parsed or already written as html with its id.
+fn skip_title<'a, 'b>(events: &'a [Event<'b>]) -> &'a [Event<'b>] {
+    let is_end = |event: &Event| match event {
+        Event::End(Tag::Heading(HeadingLevel::H1, ..)) => true,
+        Event::Html(html) => html.starts_with("</h1>"),
+        _ => false,
+    };
+
+    match events.first() {
+        Some(Event::Start(Tag::Heading(HeadingLevel::H1, ..))) => (),
+        Some(Event::Html(html)) if html.starts_with("<h1") => (),
+        _ => return events,
+    }
+    match events.iter().position(is_end) {
+        Some(end) => &events[end + 1..],
+        None => events,
+    }
+}
+
+/// Finds a '<!-- more -->' marker on its own line, outside of any lists or blockquotes so the
+/// summary doesn't end inside them.
+fn find_more_marker(events: &[Event]) -> Option<usize> {
+    let mut depth: usize = 0;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Html(html) if depth == 0 && is_more_marker(html) => return Some(i),
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn is_more_marker(html: &str) -> bool {
+    html.split_whitespace().collect::<String>() == "<!--more-->"
+}
+
+fn find_first_paragraph<'a, 'b>(events: &'a [Event<'b>]) -> Option<&'a [Event<'b>]> {
+    let mut depth: usize = 0;
+    let mut start: Option<usize> = None;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Paragraph) if depth == 0 => {
+                start = Some(i);
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                if depth > 0 {
+                    continue;
+                }
+                if let Some(start) = start.take() {
+                    let paragraph = &events[start..=i];
+                    if has_text(paragraph) {
+                        return Some(paragraph);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Leaves out the paragraphs which hold only a placeholder, like '{{ toc }}', which would be
+/// filled in with html that doesn't belong in a paragraph or a summary.
+fn remove_placeholder_paragraphs<'a>(events: &[Event<'a>]) -> Vec<Event<'a>> {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut depth: usize = 0;
+    let mut start: Option<usize> = None;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Paragraph) if depth == 0 => {
+                start = Some(i);
+                depth += 1;
+                continue;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    if let Some(start) = start.take() {
+                        let paragraph = &events[start..=i];
+                        if has_text(paragraph) {
+                            output.extend(paragraph.iter().cloned());
+                        }
+                        continue;
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        if start.is_none() {
+            output.push(event.clone());
+        }
+    }
+
+    output
+}
+
+fn has_text(events: &[Event]) -> bool {
+    let text: String = events
+        .iter()
+        .map(|event| match event {
+            Event::Text(t) | Event::Code(t) | Event::Html(t) => t.to_string(),
+            _ => String::new(),
+        })
+        .collect();
+
+    !remove_placeholders(&text).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headings::add_heading_ids;
+    use pulldown_cmark::Parser;
+
+    fn summarize(input: &str) -> Summary {
+        let events: Vec<Event> = Parser::new(input).collect();
+        create_summary(&events)
+    }
+
+    #[test]
+    fn first_paragraph_summary_test() {
+        let input = "# Title\n\n{{ toc }}\n\nThe *first* paragraph.\n\nThe second.\n";
+        assert_eq!(
+            summarize(input),
+            Summary {
+                html: String::from("<p>The <em>first</em> paragraph.</p>\n"),
+                text: String::from("The first paragraph."),
+            }
+        );
+    }
+
+    #[test]
+    fn more_marker_summary_test() {
+        let input = "Intro.\n\n- one\n- two\n\n<!-- more -->\n\nThe rest.\n";
+        let summary = summarize(input);
+        assert_eq!(
+            summary.html,
+            "<p>Intro.</p>\n<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n"
+        );
+        assert_eq!(summary.text, "Intro. one two");
+    }
+
+    #[test]
+    fn placeholder_more_marker_summary_test() {
+        let input = "{{ toc }}\n\nIntro.\n\n<!-- more -->\n\nThe rest.\n";
+        let summary = summarize(input);
+        assert_eq!(summary.html, "<p>Intro.</p>\n");
+        assert_eq!(summary.text, "Intro.");
+    }
+
+    #[test]
+    fn nested_more_marker_summary_test() {
+        let input = "# Title\n\nIntro <!-- more --> inline.\n\n- one <!-- more -->\n\n<!-- more -->\n\nThe rest.\n";
+        let summary = summarize(input);
+        assert_eq!(
+            summary.html,
+            "<p>Intro <!-- more --> inline.</p>\n<ul>\n<li>one <!-- more --></li>\n</ul>\n"
+        );
+
+        let (events, _) = add_heading_ids(
+            Parser::new("# Title\n\nIntro.\n\n<!-- more -->\n").collect(),
+            false,
+        );
+        assert_eq!(create_summary(&events).html, "<p>Intro.</p>\n");
+    }
+
+    #[test]
+    fn reading_time_test() {
+        assert_eq!(reading_time(0, 200), 1);
+        assert_eq!(reading_time(200, 200), 1);
+        assert_eq!(reading_time(201, 200), 2);
+        assert_eq!(reading_time(1000, 250), 4);
+    }
+}